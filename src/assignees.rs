@@ -0,0 +1,74 @@
+// ----------------------------------------------------------------------------
+// Issue の担当者
+// ----------------------------------------------------------------------------
+//
+// ライブラリの Issue は作成者しか持たないので、担当者はここで Issue の UUID (ids.rs) ごとに持つ。
+// ユーザーは取り込みのマージと同じく名前で指す。
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// アバターの背景色。名前から決める。
+const AVATAR_COLORS: [[u8; 3]; 6] = [
+    [0, 117, 202],
+    [14, 138, 22],
+    [162, 72, 196],
+    [215, 58, 74],
+    [233, 109, 35],
+    [60, 110, 120],
+];
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Assignees {
+    /// Issue の UUID -> ユーザー名
+    by_issue: BTreeMap<String, Vec<String>>,
+}
+
+impl Assignees {
+    pub fn get(&self, uuid: &str) -> &[String] {
+        self.by_issue
+            .get(uuid)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// 空にした Issue は記録から外す
+    pub fn set(&mut self, uuid: &str, names: Vec<String>) {
+        if names.is_empty() {
+            self.by_issue.remove(uuid);
+        } else {
+            self.by_issue.insert(uuid.to_string(), names);
+        }
+    }
+}
+
+/// 名前の頭文字 (単語が2つ以上なら最初の2語の頭文字)
+pub fn initials(name: &str) -> String {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    match words.as_slice() {
+        [] => "?".to_string(),
+        [word] => word.chars().take(2).collect::<String>().to_uppercase(),
+        [first, second, ..] => first
+            .chars()
+            .take(1)
+            .chain(second.chars().take(1))
+            .collect::<String>()
+            .to_uppercase(),
+    }
+}
+
+/// 頭文字のアバター。ホバーで名前を出す。
+pub fn avatar(ui: &mut egui::Ui, name: &str) -> egui::Response {
+    let sum: usize = name.bytes().map(usize::from).sum();
+    let [r, g, b] = AVATAR_COLORS[sum % AVATAR_COLORS.len()];
+    ui.add(egui::Label::new(
+        egui::RichText::new(format!(" {} ", initials(name)))
+            .small()
+            .strong()
+            .color(egui::Color32::WHITE)
+            .background_color(egui::Color32::from_rgb(r, g, b)),
+    ))
+    .on_hover_text(name)
+}
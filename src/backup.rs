@@ -0,0 +1,135 @@
+// ----------------------------------------------------------------------------
+// データファイルのローテーションバックアップ
+// ----------------------------------------------------------------------------
+//
+// `<data dir>/backups/<stem>-<YYYYmmdd-HHMMSS>.json` の形式で保存し、
+// 新しいものから `MAX_BACKUPS` 個だけ残す。
+
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::schema;
+use crate::storage::SnapshotInfo;
+
+/// 保持するバックアップの最大数
+pub const MAX_BACKUPS: usize = 10;
+/// 自動バックアップを取る最小間隔
+pub const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+pub fn backup_dir(data_path: &Path) -> PathBuf {
+    data_path
+        .parent()
+        .map(|p| p.join("backups"))
+        .unwrap_or_else(|| PathBuf::from("backups"))
+}
+
+fn file_stem(data_path: &Path) -> String {
+    data_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "data".to_string())
+}
+
+/// `data_path` に対応するバックアップファイルを新しい順に返す
+fn backup_files(data_path: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let prefix = format!("{}-", file_stem(data_path));
+    let Ok(entries) = fs::read_dir(backup_dir(data_path)) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<(PathBuf, SystemTime)> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.starts_with(&prefix) && name.ends_with(".json")
+        })
+        .filter_map(|e| {
+            let modified = e.metadata().and_then(|m| m.modified()).ok()?;
+            Some((e.path(), modified))
+        })
+        .collect();
+    files.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    files
+}
+
+/// 現在のデータファイルをバックアップディレクトリにコピーし、古いものを削除する。
+/// データファイルがまだ存在しない場合(またはファイルでない場合)は何もしない。
+pub fn create_backup(data_path: &Path) -> io::Result<Option<PathBuf>> {
+    if !data_path.is_file() {
+        return Ok(None);
+    }
+    let dir = backup_dir(data_path);
+    fs::create_dir_all(&dir)?;
+
+    let name = format!(
+        "{}-{}.json",
+        file_stem(data_path),
+        Local::now().format(TIMESTAMP_FORMAT)
+    );
+    let backup_path = dir.join(name);
+    fs::copy(data_path, &backup_path)?;
+    prune(data_path, MAX_BACKUPS)?;
+    Ok(Some(backup_path))
+}
+
+/// 最新のバックアップが `BACKUP_INTERVAL` より古い場合のみバックアップを取る
+pub fn rotate(data_path: &Path) -> io::Result<Option<PathBuf>> {
+    let is_due = match backup_files(data_path).first() {
+        Some((_, modified)) => modified
+            .elapsed()
+            .map(|elapsed| elapsed >= BACKUP_INTERVAL)
+            .unwrap_or(true),
+        None => true,
+    };
+    if is_due {
+        create_backup(data_path)
+    } else {
+        Ok(None)
+    }
+}
+
+fn prune(data_path: &Path, keep: usize) -> io::Result<()> {
+    for (path, _) in backup_files(data_path).into_iter().skip(keep) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// 読み込めなかったデータファイルを `<name>.corrupt-<timestamp>` にリネームして退避する
+pub fn quarantine(data_path: &Path) -> io::Result<PathBuf> {
+    let mut name = data_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".corrupt-{}",
+        Local::now().format(TIMESTAMP_FORMAT)
+    ));
+    let quarantine_path = data_path.with_file_name(name);
+    fs::rename(data_path, &quarantine_path)?;
+    Ok(quarantine_path)
+}
+
+pub fn list_backups(data_path: &Path) -> Vec<SnapshotInfo> {
+    backup_files(data_path)
+        .into_iter()
+        .map(|(path, modified)| {
+            let app = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| schema::load(&content).ok());
+            SnapshotInfo {
+                id: path.display().to_string(),
+                modified: DateTime::<Local>::from(modified),
+                issue_count: app.as_ref().map(|a| a.issues.get_list().len()),
+                user_count: app.as_ref().map(|a| a.users.get_list().len()),
+            }
+        })
+        .collect()
+}
+
+pub fn load_backup(path: &Path) -> Result<crate::TreeNotesApp, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    schema::load(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
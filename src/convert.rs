@@ -0,0 +1,142 @@
+// ----------------------------------------------------------------------------
+// 他の形式から Issue を組み立てるときの共通処理
+// ----------------------------------------------------------------------------
+//
+// CSV・GitHub・todo.txt・Taskwarrior の取り込みで使う。
+// 作成日時や状態のようにライブラリに設定用の API が無いものは `merge::with_field` で書き換える。
+// fork 元は元の形式での番号で覚えておき、全て読み終えてから index に付け替える。
+
+use chrono::{DateTime, Local};
+use local_issues_lib::{
+    Comment, Issue, Issues, Status,
+    user::{User, Users},
+};
+use std::collections::HashMap;
+
+use crate::merge::{with_field, with_parent};
+
+/// 作成日時を指定した Issue
+pub fn with_created_at(issue: &Issue, date: &DateTime<Local>) -> Result<Issue, String> {
+    let date = serde_json::to_value(date).map_err(|e| e.to_string())?;
+    with_field(issue, "created_at", date)
+}
+
+/// 日時を指定したコメント
+pub fn comment_at(text: &str, author: User, date: &DateTime<Local>) -> Result<Comment, String> {
+    let date = serde_json::to_value(date).map_err(|e| e.to_string())?;
+    with_field(&Comment::new(text, author), "date", date)
+}
+
+/// 状態を指定した Issue
+pub fn with_status(mut issue: Issue, status: &Status) -> Result<Issue, String> {
+    match status {
+        Status::Open => issue.reopen(),
+        Status::CloseAsCmp => issue.close_as_cmp(),
+        Status::CloseAsNotPlaned => issue.close_as_not_planed(),
+        // fork 済みにする API は無い
+        Status::CloseAsForked => {
+            let value = serde_json::to_value(status).map_err(|e| e.to_string())?;
+            issue = with_field(&issue, "status", value)?;
+        }
+    }
+    Ok(issue)
+}
+
+pub struct Builder {
+    issues: Vec<Issue>,
+    users: Users,
+    /// 元の形式での番号 -> index
+    by_number: HashMap<usize, usize>,
+    /// (index, fork 元の番号)
+    parents: Vec<(usize, usize)>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            issues: Vec::new(),
+            users: Users::new(),
+            by_number: HashMap::new(),
+            parents: Vec::new(),
+        }
+    }
+}
+
+impl Builder {
+    /// 名前が同じユーザーがいればそれを、いなければ追加して返す
+    pub fn user(&mut self, name: &str) -> User {
+        if let Some(user) = self.users.get_list().iter().find(|u| u.name() == name) {
+            return user.clone();
+        }
+        let user = User::new(name, "");
+        self.users.add_user(user.clone());
+        user
+    }
+
+    pub fn add_user(&mut self, user: &User) {
+        if !self
+            .users
+            .get_list()
+            .iter()
+            .any(|u| u.name() == user.name())
+        {
+            self.users.add_user(user.clone());
+        }
+    }
+
+    /// `number` を省くと追加した順 (1 始まり) を番号にする
+    pub fn push(&mut self, issue: Issue, number: Option<usize>, parent: Option<usize>) {
+        let index = self.issues.len();
+        self.by_number.insert(number.unwrap_or(index + 1), index);
+        if let Some(parent) = parent {
+            self.parents.push((index, parent));
+        }
+        self.issues.push(issue);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// 番号で覚えていた fork 元を付け替えて返す。
+    /// `Issues::new()` の 0 番目には root があるので、追加した Issue の index は1つずれる。
+    pub fn finish(mut self) -> Result<(Issues, Users), String> {
+        for (index, parent) in std::mem::take(&mut self.parents) {
+            if let Some(&parent) = self.by_number.get(&parent)
+                && parent != index
+            {
+                self.issues[index] = with_parent(&self.issues[index], parent + 1)?;
+            }
+        }
+        let mut issues = Issues::new();
+        for issue in self.issues {
+            issues.add_new_issue(issue);
+        }
+        Ok((issues, self.users))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish_points_forks_at_their_parents_after_the_root() {
+        let mut builder = Builder::default();
+        let author = builder.user("a");
+        builder.push(
+            Issue::new("parent", author.clone(), Vec::<String>::new()),
+            Some(7),
+            None,
+        );
+        builder.push(
+            Issue::new("child", author, Vec::<String>::new()),
+            Some(8),
+            Some(7),
+        );
+        let (issues, _) = builder.finish().unwrap();
+
+        assert_eq!(issues.get(1).unwrap().name(), "parent");
+        assert_eq!(issues.get(2).unwrap().from_index(), 1);
+    }
+}
@@ -0,0 +1,361 @@
+// ----------------------------------------------------------------------------
+// CSV のエクスポートと取り込み
+// ----------------------------------------------------------------------------
+//
+// 表計算ソフトで開けるように Issue 1件を1行にして書き出す。
+// 取り込みではどの列をどの項目として読むかを選んでもらい (`Mapping`)、
+// 1行を1件の Issue にする。区切りはカンマ、値は必要なときだけ `"` で囲む (RFC 4180)。
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use local_issues_lib::{
+    Comment, Issue, Issues, Status,
+    user::{User, Users},
+};
+
+use crate::convert::{self, Builder};
+use crate::ids::IssueIds;
+use crate::merge::status_label;
+use crate::trash::Trash;
+
+pub const HEADERS: [&str; 8] = [
+    "number",
+    "title",
+    "status",
+    "author",
+    "created_at",
+    "labels",
+    "forked_from",
+    "comment_count",
+];
+
+/// 1つのセルに入れるラベルの区切り
+const LABEL_SEPARATOR: &str = "; ";
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_row(out: &mut String, fields: &[String]) {
+    let fields: Vec<String> = fields.iter().map(|f| quote(f)).collect();
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+/// Issue の作成者
+fn author_name(issue: &Issue) -> String {
+    issue.created_by().name()
+}
+
+/// ゴミ箱に入っていない全 Issue を `HEADERS` の列で書き出す
+pub fn render(issues: &Issues, ids: &IssueIds, trash: &Trash) -> String {
+    let mut out = String::new();
+    write_row(&mut out, &HEADERS.map(String::from));
+    for i in trash.kept_issues(issues, ids) {
+        let issue = &issues.get_list()[i];
+        write_row(
+            &mut out,
+            &[
+                ids.number(i).to_string(),
+                issue.name().to_string(),
+                status_label(issue.status()).to_string(),
+                author_name(issue),
+                issue.created_at().to_rfc3339(),
+                issue.get_labels().join(LABEL_SEPARATOR),
+                trash
+                    .kept_parent(issues, ids, i)
+                    .map(|parent| ids.number(parent).to_string())
+                    .unwrap_or_default(),
+                trash.kept_comments(ids, i, issue).len().to_string(),
+            ],
+        );
+    }
+    out
+}
+
+/// 読み込んだ CSV。1行目を見出しとして扱う。
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn cell(&self, row: usize, column: usize) -> &str {
+        self.rows
+            .get(row)
+            .and_then(|r| r.get(column))
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+pub fn parse(text: &str) -> Result<Table, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records: Vec<Vec<String>> = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(format!("line {}: unterminated quoted field", line));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    if records.is_empty() {
+        return Err("the file is empty".to_string());
+    }
+    let headers = records.remove(0);
+    Ok(Table {
+        headers,
+        rows: records,
+    })
+}
+
+// ----------------------------------------------------------------------------
+// 列の対応付け
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Field {
+    Number,
+    Title,
+    Status,
+    Author,
+    CreatedAt,
+    Labels,
+    ForkedFrom,
+    Body,
+}
+
+impl Field {
+    pub const ALL: [Field; 8] = [
+        Field::Number,
+        Field::Title,
+        Field::Status,
+        Field::Author,
+        Field::CreatedAt,
+        Field::Labels,
+        Field::ForkedFrom,
+        Field::Body,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Number => "Number",
+            Field::Title => "Title",
+            Field::Status => "Status",
+            Field::Author => "Author",
+            Field::CreatedAt => "Created at",
+            Field::Labels => "Labels",
+            Field::ForkedFrom => "Forked from",
+            Field::Body => "Body (first comment)",
+        }
+    }
+
+    /// 見出しから推測するときの候補 (小文字・英数字のみにしたもの)
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Field::Number => &["number", "no", "id"],
+            Field::Title => &["title", "name", "summary", "subject"],
+            Field::Status => &["status", "state"],
+            Field::Author => &["author", "user", "createdby", "reporter"],
+            Field::CreatedAt => &["createdat", "created", "date"],
+            Field::Labels => &["labels", "label", "tags"],
+            Field::ForkedFrom => &["forkedfrom", "parent"],
+            Field::Body => &["body", "description", "comment", "text"],
+        }
+    }
+}
+
+fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// 項目ごとに読む列。None の項目は使わない。
+pub struct Mapping {
+    columns: [Option<usize>; Field::ALL.len()],
+}
+
+impl Mapping {
+    /// 見出しの名前から対応を推測する
+    pub fn guess(headers: &[String]) -> Self {
+        let headers: Vec<String> = headers.iter().map(|h| normalize(h)).collect();
+        let columns = Field::ALL.map(|field| {
+            headers
+                .iter()
+                .position(|h| field.aliases().contains(&h.as_str()))
+        });
+        Self { columns }
+    }
+
+    pub fn get(&self, field: Field) -> Option<usize> {
+        self.columns[field as usize]
+    }
+
+    pub fn get_mut(&mut self, field: Field) -> &mut Option<usize> {
+        &mut self.columns[field as usize]
+    }
+}
+
+fn parse_status(text: &str) -> Result<Status, String> {
+    match normalize(text).as_str() {
+        "" | "open" => Ok(Status::Open),
+        "completed" | "closed" | "done" | "closeascmp" => Ok(Status::CloseAsCmp),
+        "notplanned" | "wontfix" | "closeasnotplaned" => Ok(Status::CloseAsNotPlaned),
+        "forked" | "closeasforked" => Ok(Status::CloseAsForked),
+        _ => Err(format!("unknown status `{}`", text)),
+    }
+}
+
+fn parse_date(text: &str) -> Result<DateTime<Local>, String> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Ok(date.with_timezone(&Local));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        });
+    naive
+        .and_then(|n| Local.from_local_datetime(&n).earliest())
+        .ok_or_else(|| format!("cannot read date `{}`", text))
+}
+
+/// `#3` や `3` を 1 始まりの番号として読む
+fn parse_number(text: &str) -> Option<usize> {
+    text.trim().trim_start_matches('#').parse().ok()
+}
+
+/// 対応付けに従って1行を1件の Issue にする。
+/// 作成者の列が無い行は `fallback` が作ったことにする。
+pub fn build(table: &Table, mapping: &Mapping, fallback: &User) -> Result<(Issues, Users), String> {
+    let Some(title_column) = mapping.get(Field::Title) else {
+        return Err("choose the column to use as the title".to_string());
+    };
+    let cell = |row: usize, field: Field| -> &str {
+        mapping
+            .get(field)
+            .map(|column| table.cell(row, column).trim())
+            .unwrap_or_default()
+    };
+
+    let mut builder = Builder::default();
+    builder.add_user(fallback);
+
+    for row in 0..table.rows.len() {
+        // 見出しが1行目なので、データは2行目から
+        let line = row + 2;
+        let title = table.cell(row, title_column).trim();
+        if title.is_empty() {
+            continue;
+        }
+
+        let author = match cell(row, Field::Author) {
+            "" => fallback.clone(),
+            name => builder.user(name),
+        };
+        let labels: Vec<String> = cell(row, Field::Labels)
+            .split([';', ','])
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect();
+
+        let mut issue = Issue::new(title, author.clone(), labels);
+        let body = match cell(row, Field::Body) {
+            "" => title,
+            body => body,
+        };
+        if cell(row, Field::CreatedAt).is_empty() {
+            issue.comment(Comment::new(body, author));
+        } else {
+            let created_at = parse_date(cell(row, Field::CreatedAt))
+                .map_err(|e| format!("line {}: {}", line, e))?;
+            issue = convert::with_created_at(&issue, &created_at)?;
+            issue.comment(convert::comment_at(body, author, &created_at)?);
+        }
+
+        let status =
+            parse_status(cell(row, Field::Status)).map_err(|e| format!("line {}: {}", line, e))?;
+        builder.push(
+            convert::with_status(issue, &status)?,
+            parse_number(cell(row, Field::Number)),
+            parse_number(cell(row, Field::ForkedFrom)),
+        );
+    }
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeNotesApp;
+    use crate::fixtures;
+
+    #[test]
+    fn forks_and_authors_survive_a_round_trip() {
+        let mut app = TreeNotesApp::default();
+        let issue = fixtures::issue(&app, "parent, with a comma", &["bug"]);
+        let parent = fixtures::add(&mut app, issue);
+        let child = fixtures::fork(&mut app, parent);
+
+        let table = parse(&render(&app.issues, &app.ids, &app.trash)).unwrap();
+        let fallback = User::new("fallback", "");
+        let (imported, _) = build(&table, &Mapping::guess(&table.headers), &fallback).unwrap();
+        let mut imported_ids = IssueIds::default();
+        imported_ids.sync(&imported);
+
+        let parent_issue = imported.get(parent).unwrap();
+        assert_eq!(parent_issue.name(), "parent, with a comma");
+        assert_eq!(parent_issue.created_by().name(), app.current_user.name());
+        assert_eq!(parent_issue.get_labels(), vec!["bug".to_string()]);
+        assert_eq!(imported_ids.parent(&imported, child), Some(parent));
+    }
+}
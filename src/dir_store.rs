@@ -0,0 +1,499 @@
+// ----------------------------------------------------------------------------
+// リポジトリ内に置くトラッカー (`.fork_notes/`)
+// ----------------------------------------------------------------------------
+//
+// .fork_notes/
+//   tracker.json        スキーマバージョンとユーザー一覧、ラベルの登録簿
+//   issues/<UUID>.json  Issue 1件ごとに1ファイル。番号・fork 元・担当者・ゴミ箱・編集履歴も一緒に持つ
+//   local.json          現在のユーザーやフィルタなど個人の状態 (.gitignore で除外)
+//   journal.jsonl       このマシンでの操作ジャーナル (.gitignore で除外)
+//
+// diff や merge がしやすいように、整形したJSONを書き、中身が変わったファイルだけ書き換える。
+// 別々のブランチで Issue を足しても、ファイル名が UUID なのでぶつからない。
+// 読み込みでは JsonFileStore と同じ形に組み立てて、スキーマのマイグレーションに通す。
+
+use chrono::{DateTime, Local};
+use local_issues_lib::{
+    Issue,
+    user::{User, Users},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::assignees::Assignees;
+use crate::ids::IssueId;
+use crate::labels::Labels;
+use crate::revisions::{Revision, Revisions};
+use crate::schema::{self, LoadError};
+use crate::storage;
+use crate::trash::{Deleted, Trash};
+use crate::{FilterStatus, TreeNotesApp};
+
+pub const DIR_NAME: &str = ".fork_notes";
+const MANIFEST_FILE: &str = "tracker.json";
+const LOCAL_FILE: &str = "local.json";
+const JOURNAL_FILE: &str = "journal.jsonl";
+const ISSUES_DIR: &str = "issues";
+
+/// Issue のファイルを UUID で名付けるようになったスキーマバージョン。
+/// それより前は `issues/0001.json` のように並び順で名付け、ゴミ箱や編集履歴は tracker.json に持っていた。
+const UUID_FILES_VERSION: u32 = 6;
+
+#[derive(Serialize)]
+struct ManifestRef<'a> {
+    schema_version: u32,
+    users: &'a Users,
+    labels: &'a Labels,
+}
+
+/// ゴミ箱に入っているもの
+#[derive(Deserialize, Serialize)]
+struct DeletedHere {
+    /// None なら Issue そのもの
+    comment: Option<usize>,
+    by: User,
+    date: DateTime<Local>,
+}
+
+/// タイトル (`comment` が None) かコメントの編集履歴
+#[derive(Deserialize, Serialize)]
+struct EditsHere {
+    comment: Option<usize>,
+    revisions: Vec<Revision>,
+}
+
+/// `issues/<UUID>.json` の中身
+#[derive(Deserialize, Serialize)]
+struct IssueFile {
+    number: usize,
+    uuid: String,
+    /// fork 元の UUID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    assignees: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deleted: Vec<DeletedHere>,
+    /// 完全に削除したもの。None なら Issue そのもの
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    purged: Vec<Option<usize>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<EditsHere>,
+    issue: Issue,
+}
+
+#[derive(Serialize)]
+struct LocalStateRef<'a> {
+    current_user: &'a User,
+    filter_status: &'a FilterStatus,
+    journal_seq: u64,
+}
+
+#[derive(Deserialize)]
+struct LocalState {
+    current_user: Option<User>,
+    filter_status: Option<FilterStatus>,
+    journal_seq: Option<u64>,
+}
+
+/// `path` が `.fork_notes` ディレクトリを指しているか
+pub fn is_tracker_dir(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == DIR_NAME)
+}
+
+/// コマンドライン等で渡されたパスを、開くべきトラッカーのパスに変換する。
+/// `.fork_notes` 以外のディレクトリが渡された場合はその中の `.fork_notes` を使う。
+pub fn resolve(path: &Path) -> PathBuf {
+    if path.is_dir() && !is_tracker_dir(path) {
+        path.join(DIR_NAME)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+pub fn journal_path(dir: &Path) -> PathBuf {
+    dir.join(JOURNAL_FILE)
+}
+
+/// `issues/` 内の JSON ファイル
+fn issue_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let issues_dir = dir.join(ISSUES_DIR);
+    if !issues_dir.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_dir(issues_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, LoadError> {
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| LoadError::Invalid(format!("{}: {}", path.display(), e)))
+}
+
+/// 並び順で名付けていた頃の `issues/0001.json`。Issue 以外は tracker.json に入っている。
+fn load_numbered_issues(
+    dir: &Path,
+    value: &mut serde_json::Map<String, Value>,
+) -> Result<(), LoadError> {
+    let mut files: Vec<(usize, PathBuf)> = issue_files(dir)?
+        .into_iter()
+        .filter_map(|path| {
+            let number = path.file_stem()?.to_str()?.parse::<usize>().ok()?;
+            Some((number, path))
+        })
+        .collect();
+    files.sort_by_key(|(number, _)| *number);
+    let mut stored = Vec::new();
+    for (number, path) in files {
+        stored.push((number.saturating_sub(1), read_json::<Issue>(&path)?));
+    }
+    let issues = storage::issues_in_order(stored)?;
+    value.insert("issues".to_string(), serde_json::to_value(&issues)?);
+    Ok(())
+}
+
+/// `issues/<UUID>.json` から Issue と、番号・担当者・ゴミ箱・編集履歴を組み立てる
+fn load_uuid_issues(
+    dir: &Path,
+    value: &mut serde_json::Map<String, Value>,
+) -> Result<(), LoadError> {
+    let mut files = Vec::new();
+    for path in issue_files(dir)? {
+        files.push(read_json::<IssueFile>(&path)?);
+    }
+    files.sort_by(|a, b| (a.number, &a.uuid).cmp(&(b.number, &b.uuid)));
+    // 別々のブランチで足した Issue は同じ番号を持つことがあるので、後のものに新しい番号を振って末尾に回す
+    let mut next_number = files.iter().map(|f| f.number).max().unwrap_or(0) + 1;
+    let mut previous = None;
+    for file in &mut files {
+        if previous == Some(file.number) {
+            file.number = next_number;
+            next_number += 1;
+        } else {
+            previous = Some(file.number);
+        }
+    }
+    files.sort_by_key(|f| f.number);
+
+    let mut stored = Vec::new();
+    let mut ids = Vec::new();
+    let mut deleted = Vec::new();
+    let mut trash = Trash::default();
+    let mut revisions = Revisions::default();
+    let mut assignees = Assignees::default();
+    for file in files {
+        for here in file.deleted {
+            deleted.push(Deleted {
+                issue: file.uuid.clone(),
+                comment: here.comment,
+                by: here.by,
+                date: here.date,
+            });
+        }
+        for comment in file.purged {
+            trash.purge(&file.uuid, comment);
+        }
+        for edits in file.revisions {
+            for revision in edits.revisions {
+                revisions.record(&file.uuid, edits.comment, revision);
+            }
+        }
+        assignees.set(&file.uuid, file.assignees);
+        stored.push((file.number.saturating_sub(1), file.issue));
+        ids.push(IssueId {
+            number: file.number,
+            uuid: file.uuid,
+            parent: file.parent,
+        });
+    }
+    // ゴミ箱は消した順に並ぶ
+    deleted.sort_by_key(|d| d.date);
+    for entry in deleted {
+        trash.delete(entry);
+    }
+
+    let issues = storage::issues_in_order(stored)?;
+    value.insert("issues".to_string(), serde_json::to_value(&issues)?);
+    value.insert("ids".to_string(), serde_json::to_value(&ids)?);
+    value.insert("trash".to_string(), serde_json::to_value(&trash)?);
+    value.insert("revisions".to_string(), serde_json::to_value(&revisions)?);
+    value.insert("assignees".to_string(), serde_json::to_value(&assignees)?);
+    Ok(())
+}
+
+pub fn load(dir: &Path) -> Result<TreeNotesApp, LoadError> {
+    let Value::Object(mut value) = read_json::<Value>(&dir.join(MANIFEST_FILE))? else {
+        return Err(LoadError::Invalid(format!(
+            "{} is not an object",
+            MANIFEST_FILE
+        )));
+    };
+    let version = value
+        .get(schema::VERSION_KEY)
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    if version > u64::from(schema::CURRENT_VERSION) {
+        return Err(LoadError::TooNew {
+            found: u32::try_from(version).unwrap_or(u32::MAX),
+        });
+    }
+    if version < u64::from(UUID_FILES_VERSION) {
+        load_numbered_issues(dir, &mut value)?;
+    } else {
+        load_uuid_issues(dir, &mut value)?;
+    }
+
+    let mut app = schema::from_value(Value::Object(value))?;
+    if let Some(first) = app.users.get_list().first() {
+        app.current_user = first.clone();
+    }
+
+    // 個人の状態は壊れていても無視する
+    if let Ok(content) = fs::read_to_string(dir.join(LOCAL_FILE))
+        && let Ok(local) = serde_json::from_str::<LocalState>(&content)
+    {
+        if let Some(user) = local.current_user {
+            app.current_user = user;
+        }
+        if let Some(filter_status) = local.filter_status {
+            app.filter_status = filter_status;
+        }
+        if let Some(journal_seq) = local.journal_seq {
+            app.journal_seq = journal_seq;
+        }
+    }
+
+    Ok(app)
+}
+
+/// `index` 番目の Issue のファイルの中身
+fn issue_file(app: &TreeNotesApp, index: usize, issue: &Issue) -> io::Result<IssueFile> {
+    let id = app
+        .ids
+        .get(index)
+        .ok_or_else(|| io::Error::other(format!("issue at position {} has no UUID", index)))?;
+    let uuid = id.uuid.as_str();
+    Ok(IssueFile {
+        number: id.number,
+        uuid: id.uuid.clone(),
+        parent: id.parent.clone(),
+        assignees: app.assignees.get(uuid).to_vec(),
+        deleted: app
+            .trash
+            .get_list()
+            .iter()
+            .filter(|d| d.issue == uuid)
+            .map(|d| DeletedHere {
+                comment: d.comment,
+                by: d.by.clone(),
+                date: d.date,
+            })
+            .collect(),
+        purged: app
+            .trash
+            .get_purged()
+            .iter()
+            .filter(|(issue, _)| issue == uuid)
+            .map(|(_, comment)| *comment)
+            .collect(),
+        revisions: app
+            .revisions
+            .get_list()
+            .filter(|(issue, _, _)| *issue == uuid)
+            .map(|(_, comment, revisions)| EditsHere {
+                comment,
+                revisions: revisions.to_vec(),
+            })
+            .collect(),
+        issue: issue.clone(),
+    })
+}
+
+/// 中身が変わっている場合だけ書き換える
+fn write_if_changed(path: &Path, contents: &str) -> io::Result<()> {
+    if fs::read(path).is_ok_and(|current| current == contents.as_bytes()) {
+        return Ok(());
+    }
+    storage::write_atomic(path, contents.as_bytes())
+}
+
+fn to_pretty_json<T: Serialize>(value: &T) -> io::Result<String> {
+    let mut json = serde_json::to_string_pretty(value)?;
+    json.push('\n');
+    Ok(json)
+}
+
+pub fn save(dir: &Path, app: &TreeNotesApp) -> io::Result<()> {
+    let issues_dir = dir.join(ISSUES_DIR);
+    fs::create_dir_all(&issues_dir)?;
+
+    write_if_changed(
+        &dir.join(".gitignore"),
+        &format!("{}\n{}\n", LOCAL_FILE, JOURNAL_FILE),
+    )?;
+    write_if_changed(
+        &dir.join(MANIFEST_FILE),
+        &to_pretty_json(&ManifestRef {
+            schema_version: schema::CURRENT_VERSION,
+            users: &app.users,
+            labels: &app.labels,
+        })?,
+    )?;
+    write_if_changed(
+        &dir.join(LOCAL_FILE),
+        &to_pretty_json(&LocalStateRef {
+            current_user: &app.current_user,
+            filter_status: &app.filter_status,
+            journal_seq: app.journal_seq,
+        })?,
+    )?;
+
+    let mut names = Vec::new();
+    for (i, issue) in app.issues.get_list().iter().enumerate() {
+        let file = issue_file(app, i, issue)?;
+        let name = format!("{}.json", file.uuid);
+        write_if_changed(&issues_dir.join(&name), &to_pretty_json(&file)?)?;
+        names.push(name);
+    }
+    // 並び順で名付けていた頃のファイルもここで消える
+    for path in issue_files(dir)? {
+        if !path
+            .file_name()
+            .is_some_and(|name| names.iter().any(|n| name == n.as_str()))
+        {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, add_issue};
+    use crate::journal::Op;
+
+    fn temp_dir() -> PathBuf {
+        fixtures::temp_path("")
+    }
+
+    #[test]
+    fn issues_and_their_metadata_survive_a_round_trip() {
+        let dir = temp_dir();
+        let mut app = TreeNotesApp::default();
+        let index = add_issue(&mut app, "a");
+        let user = app.current_user.clone();
+        for op in [
+            Op::EditTitle {
+                issue: app.ids.key(index),
+                title: "renamed".to_string(),
+                editor: user.clone(),
+                date: Local::now(),
+            },
+            Op::SetAssignees {
+                issue: app.ids.key(index),
+                assignees: vec![user.name()],
+            },
+            Op::Delete {
+                issue: app.ids.key(index),
+                comment: None,
+                by: user,
+                date: Local::now(),
+            },
+        ] {
+            op.apply(&mut app);
+        }
+        save(&dir, &app).unwrap();
+
+        let uuid = app.ids.uuid(index).unwrap();
+        assert!(dir.join(ISSUES_DIR).join(format!("{}.json", uuid)).exists());
+        let loaded = load(&dir).unwrap();
+        let list = loaded.issues.get_list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[1].name(), "renamed");
+        assert_eq!(loaded.ids.uuid(1), Some(uuid));
+        assert_eq!(loaded.revisions.get(uuid, None)[0].before, "a");
+        assert_eq!(loaded.assignees_of(1), app.assignees_of(1));
+        assert!(loaded.trash.get(uuid, None).is_some());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn issues_added_on_two_branches_both_load() {
+        let dir = temp_dir();
+        let mut app = TreeNotesApp::default();
+        add_issue(&mut app, "shared");
+        save(&dir, &app).unwrap();
+        // 別のブランチで同じ番号の Issue を足してからマージしたのと同じ状態
+        let mut ours = load(&dir).unwrap();
+        let mut theirs = load(&dir).unwrap();
+        add_issue(&mut ours, "ours");
+        add_issue(&mut theirs, "theirs");
+        save(&dir, &theirs).unwrap();
+        let theirs_file = fs::read_to_string(
+            dir.join(ISSUES_DIR)
+                .join(format!("{}.json", theirs.ids.uuid(2).unwrap())),
+        )
+        .unwrap();
+        save(&dir, &ours).unwrap();
+        fs::write(
+            dir.join(ISSUES_DIR)
+                .join(format!("{}.json", theirs.ids.uuid(2).unwrap())),
+            theirs_file,
+        )
+        .unwrap();
+
+        let loaded = load(&dir).unwrap();
+        let mut names: Vec<&str> = loaded.issues.get_list().iter().map(|i| i.name()).collect();
+        names.sort();
+        assert_eq!(names, ["ours", "root", "shared", "theirs"]);
+        assert_eq!(loaded.ids.number(3), 4);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn numbered_files_are_migrated() {
+        let dir = temp_dir();
+        let mut app = TreeNotesApp::default();
+        add_issue(&mut app, "old");
+        fs::create_dir_all(dir.join(ISSUES_DIR)).unwrap();
+        for (i, issue) in app.issues.get_list().iter().enumerate() {
+            fs::write(
+                dir.join(ISSUES_DIR).join(format!("{:04}.json", i + 1)),
+                serde_json::to_string(issue).unwrap(),
+            )
+            .unwrap();
+        }
+        let manifest = serde_json::json!({
+            "schema_version": 5,
+            "users": app.users,
+            "labels": [],
+            "revisions": [],
+            "trash": {
+                "deleted": [{
+                    "issue": 1,
+                    "comment": null,
+                    "by": app.current_user,
+                    "date": Local::now(),
+                }],
+                "purged": [],
+            },
+        });
+        fs::write(dir.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+
+        let loaded = load(&dir).unwrap();
+        assert_eq!(loaded.issues.get_list()[1].name(), "old");
+        assert!(loaded.trash.is_deleted_at(&loaded.ids, 1, None));
+
+        save(&dir, &loaded).unwrap();
+        assert!(!dir.join(ISSUES_DIR).join("0002.json").exists());
+        assert_eq!(load(&dir).unwrap().issues.get_list().len(), 2);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,81 @@
+// ----------------------------------------------------------------------------
+// エクスポートしたファイルの書き出し
+// ----------------------------------------------------------------------------
+//
+// ファイルの中身は各形式のモジュールで作り、保存先の選択と書き込みはここでまとめて行う。
+// ダイアログで UI が止まらないよう、どちらも別スレッド (wasm では非同期タスク) で動かす。
+
+/// 複数ファイルを書き出すときの1ファイル分
+pub struct OutputFile {
+    /// 選んだフォルダからの相対パス (`/` 区切り)
+    pub path: String,
+    pub contents: String,
+}
+
+/// 保存ダイアログを出して1ファイル書き出す
+pub fn save_file(default_name: &str, contents: String) {
+    let default_name = default_name.to_string();
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Some(handle) = rfd::AsyncFileDialog::new()
+            .set_file_name(&default_name)
+            .save_file()
+            .await
+        {
+            let _ = handle.write(contents.as_bytes()).await;
+        }
+    });
+
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::spawn(move || {
+        if let Some(path) = rfd::FileDialog::new()
+            .set_file_name(&default_name)
+            .save_file()
+            && let Err(e) = std::fs::write(&path, contents)
+        {
+            log::error!("Failed to export to {}: {}", path.display(), e);
+        }
+    });
+}
+
+/// フォルダを選んでもらい、その中に書き出す。
+/// ブラウザからはフォルダに書き込めないのでネイティブ版のみ。
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_to_folder(files: Vec<OutputFile>) {
+    std::thread::spawn(move || {
+        let Some(dir) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+        for file in files {
+            let path = dir.join(&file.path);
+            let result = match path.parent() {
+                Some(parent) => std::fs::create_dir_all(parent),
+                None => Ok(()),
+            }
+            .and_then(|_| std::fs::write(&path, &file.contents));
+            if let Err(e) = result {
+                log::error!("Failed to export to {}: {}", path.display(), e);
+                return;
+            }
+        }
+    });
+}
+
+/// ファイル名に使える形にする (英数字以外は `-` にまとめる)
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.trim_matches('-').chars().take(50).collect();
+    if slug.is_empty() {
+        "issue".to_string()
+    } else {
+        slug.trim_end_matches('-').to_string()
+    }
+}
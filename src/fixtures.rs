@@ -0,0 +1,62 @@
+// ----------------------------------------------------------------------------
+// テスト用のデータ
+// ----------------------------------------------------------------------------
+//
+// 各モジュールのテストで共通に使う組み立て。画面から操作したときと同じくジャーナルの操作を
+// 適用して Issue を足すので、番号と UUID も振られる。
+
+use local_issues_lib::{Comment, Issue};
+use std::path::PathBuf;
+
+use crate::TreeNotesApp;
+use crate::journal::Op;
+
+/// 最初のコメントがタイトルと同じ Issue (画面から作ったものと同じ)。作成者は今のユーザー。
+pub fn issue(app: &TreeNotesApp, title: &str, labels: &[&str]) -> Issue {
+    let mut issue = Issue::new(title, app.current_user.clone(), labels.to_vec());
+    issue.comment(Comment::new(title, app.current_user.clone()));
+    issue
+}
+
+/// `issue` を末尾に足して、その位置を返す
+pub fn add(app: &mut TreeNotesApp, issue: Issue) -> usize {
+    Op::AddNewIssue {
+        issue,
+        uuid: None,
+        parent: None,
+    }
+    .apply(app)
+    .unwrap()
+}
+
+pub fn add_issue(app: &mut TreeNotesApp, title: &str) -> usize {
+    add(app, issue(app, title, &[]))
+}
+
+/// `index` を fork して、fork 先の位置を返す
+pub fn fork(app: &mut TreeNotesApp, index: usize) -> usize {
+    Op::Fork {
+        issue: app.ids.key(index),
+        uuid: None,
+    }
+    .apply(app)
+    .unwrap()
+}
+
+/// `titles` の Issue を順に足したアプリ
+pub fn app_with_issues(titles: &[impl AsRef<str>]) -> TreeNotesApp {
+    let mut app = TreeNotesApp::default();
+    for title in titles {
+        add_issue(&mut app, title.as_ref());
+    }
+    app
+}
+
+/// テストごとに別の一時ファイル (ディレクトリ) の名前
+pub fn temp_path(extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "fork_notes_{}{}",
+        crate::ids::new_uuid(),
+        extension
+    ))
+}
@@ -0,0 +1,436 @@
+// ----------------------------------------------------------------------------
+// GitHub Issues との取り込み・書き出し
+// ----------------------------------------------------------------------------
+//
+// 取り込みでは REST API の issues / comments エンドポイントが返す JSON を保存したファイルを読む。
+//
+// - `GET /repos/{owner}/{repo}/issues?state=all`: Issue の配列
+// - `GET /repos/{owner}/{repo}/issues/comments` (または Issue ごとの `/comments`):
+//   コメントの配列。どの Issue のものかは `issue_url` の末尾の番号で判断する
+//
+// どちらもトップレベルが配列なので、オブジェクトであるこのアプリの JSON と区別できる。
+// issues の結果に混ざる Pull Request は取り込まない。
+
+use chrono::{DateTime, Local};
+use local_issues_lib::{Comment, Issue, Issues, Status, user::Users};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::convert::{self, Builder};
+use crate::export::{self, OutputFile};
+use crate::ids::IssueIds;
+use crate::trash::Trash;
+
+/// ユーザーが削除されている場合の名前 (GitHub の表示に合わせる)
+const GHOST: &str = "ghost";
+
+#[derive(Deserialize)]
+struct GhUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GhLabel {
+    Name(String),
+    Object { name: String },
+}
+
+#[derive(Deserialize)]
+struct GhIssue {
+    number: u64,
+    title: String,
+    body: Option<String>,
+    state: String,
+    state_reason: Option<String>,
+    #[serde(default)]
+    labels: Vec<GhLabel>,
+    user: Option<GhUser>,
+    created_at: DateTime<Local>,
+    pull_request: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct GhComment {
+    issue_url: String,
+    user: Option<GhUser>,
+    body: Option<String>,
+    created_at: DateTime<Local>,
+}
+
+/// 配列の要素の種類。issues の結果か comments の結果かは中身で見分ける。
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GhItem {
+    Comment(GhComment),
+    Issue(GhIssue),
+}
+
+/// GitHub の JSON らしいか (トップレベルが配列か)
+pub fn looks_like(json: &str) -> bool {
+    json.trim_start().starts_with('[')
+}
+
+fn login(gh_user: Option<&GhUser>) -> &str {
+    gh_user.map(|u| u.login.as_str()).unwrap_or(GHOST)
+}
+
+/// `(ファイル名, 中身)` の組をまとめて Issue にする。
+/// Issue は番号順に並べ、コメントは日時順にそれぞれの Issue の後ろに付ける。
+pub fn convert(files: &[(String, String)]) -> Result<(Issues, Users), String> {
+    let mut gh_issues = Vec::new();
+    let mut gh_comments = Vec::new();
+    for (name, json) in files {
+        let items: Vec<GhItem> = serde_json::from_str(json)
+            .map_err(|e| format!("{}: not a list of GitHub issues or comments: {}", name, e))?;
+        for item in items {
+            match item {
+                GhItem::Issue(issue) if issue.pull_request.is_none() => gh_issues.push(issue),
+                GhItem::Issue(_) => {}
+                GhItem::Comment(comment) => gh_comments.push(comment),
+            }
+        }
+    }
+    if gh_issues.is_empty() {
+        return Err("no issues found (pull requests are skipped)".to_string());
+    }
+    gh_issues.sort_by_key(|issue| issue.number);
+    gh_comments.sort_by_key(|comment| comment.created_at);
+
+    let mut builder = Builder::default();
+    // GitHub の番号 -> index
+    let mut by_number = HashMap::new();
+    let mut issues = Vec::new();
+    for gh in &gh_issues {
+        let author = builder.user(login(gh.user.as_ref()));
+        let labels = gh
+            .labels
+            .iter()
+            .map(|label| match label {
+                GhLabel::Name(name) | GhLabel::Object { name } => name.clone(),
+            })
+            .collect();
+        let mut issue = Issue::new(&gh.title, author.clone(), labels);
+        issue = convert::with_created_at(&issue, &gh.created_at)?;
+        let body = match gh.body.as_deref().map(str::trim) {
+            Some(body) if !body.is_empty() => body,
+            _ => gh.title.as_str(),
+        };
+        issue.comment(convert::comment_at(body, author, &gh.created_at)?);
+
+        let status = match (gh.state.as_str(), gh.state_reason.as_deref()) {
+            ("open", _) => Status::Open,
+            (_, Some("not_planned") | Some("duplicate")) => Status::CloseAsNotPlaned,
+            _ => Status::CloseAsCmp,
+        };
+        by_number.insert(gh.number, issues.len());
+        issues.push(convert::with_status(issue, &status)?);
+    }
+
+    for gh in &gh_comments {
+        let number = gh.issue_url.rsplit('/').next().and_then(|n| n.parse().ok());
+        let Some(&index) = number.and_then(|n: u64| by_number.get(&n)) else {
+            continue;
+        };
+        let author = builder.user(login(gh.user.as_ref()));
+        let text = gh.body.as_deref().unwrap_or_default();
+        issues[index].comment(convert::comment_at(text, author, &gh.created_at)?);
+    }
+
+    for issue in issues {
+        builder.push(issue, None, None);
+    }
+    builder.finish()
+}
+
+// ----------------------------------------------------------------------------
+// GitHub 向けの書き出し
+// ----------------------------------------------------------------------------
+//
+// 取り込みの逆。GitHub に作り直すときは全て実行した人の名前で投稿されるので、
+// 本文とコメントの先頭に元の作成者と日時を書き添える。
+// 最初のコメントは Issue の本文にし、2つ目以降をコメントにする。
+// fork の関係は GitHub に無いので、fork 元・fork 先への言及として残す。
+
+/// GitHub の `state_reason` と `gh issue close --reason` の値。開いている Issue は None。
+/// fork 済みは fork 先に引き継いだので not planned 扱いにする。
+fn close_reason(status: &Status) -> Option<(&'static str, &'static str)> {
+    match status {
+        Status::Open => None,
+        Status::CloseAsCmp => Some(("completed", "completed")),
+        Status::CloseAsNotPlaned | Status::CloseAsForked => Some(("not_planned", "not planned")),
+    }
+}
+
+fn attributed(comment: &Comment) -> String {
+    format!(
+        "_Originally by **{}** on {}_\n\n{}",
+        comment.author().name(),
+        comment.date().format("%Y-%m-%d %H:%M"),
+        comment.text().trim_end()
+    )
+}
+
+/// Issue の本文。fork 元があればその旨を添える。
+fn issue_body(
+    issues: &Issues,
+    ids: &IssueIds,
+    trash: &Trash,
+    index: usize,
+    issue: &Issue,
+) -> String {
+    let mut body = trash
+        .kept_comments(ids, index, issue)
+        .first()
+        .map(|c| attributed(c))
+        .unwrap_or_default();
+    if let Some(parent) = trash.kept_parent(issues, ids, index) {
+        let name = issues
+            .get(parent)
+            .map(|p| format!(" ({})", p.name()))
+            .unwrap_or_default();
+        body.push_str(&format!(
+            "\n\n> Forked from local issue #{}{}",
+            ids.number(parent),
+            name
+        ));
+    }
+    body
+}
+
+/// Issue ごとのディレクトリに REST API にそのまま渡せる JSON を置く。
+///
+/// - `issue.json`: `POST /repos/{owner}/{repo}/issues`
+/// - `comments/001.json`: `POST /repos/{owner}/{repo}/issues/{number}/comments`
+/// - `close.json`: `PATCH /repos/{owner}/{repo}/issues/{number}` (閉じている Issue のみ)
+pub fn render_payloads(issues: &Issues, ids: &IssueIds, trash: &Trash) -> Vec<OutputFile> {
+    let mut files = vec![OutputFile {
+        path: "README.md".to_string(),
+        contents: PAYLOAD_README.to_string(),
+    }];
+    for i in trash.kept_issues(issues, ids) {
+        let issue = &issues.get_list()[i];
+        let dir = format!("{:04}-{}", ids.number(i), export::slug(issue.name()));
+        let payload = json!({
+            "title": issue.name(),
+            "body": issue_body(issues, ids, trash, i, issue),
+            "labels": issue.get_labels(),
+        });
+        files.push(json_file(format!("{}/issue.json", dir), &payload));
+
+        let mut comments: Vec<String> = trash
+            .kept_comments(ids, i, issue)
+            .into_iter()
+            .skip(1)
+            .map(attributed)
+            .collect();
+        let forks = trash.kept_children(issues, ids, i);
+        if !forks.is_empty() {
+            let forks: Vec<String> = forks
+                .iter()
+                .map(|f| format!("#{}", ids.number(*f)))
+                .collect();
+            comments.push(format!("> Forked into local issue {}", forks.join(", ")));
+        }
+        for (n, body) in comments.into_iter().enumerate() {
+            files.push(json_file(
+                format!("{}/comments/{:03}.json", dir, n + 1),
+                &json!({ "body": body }),
+            ));
+        }
+
+        if let Some((reason, _)) = close_reason(issue.status()) {
+            files.push(json_file(
+                format!("{}/close.json", dir),
+                &json!({ "state": "closed", "state_reason": reason }),
+            ));
+        }
+    }
+    files
+}
+
+const PAYLOAD_README: &str = "# GitHub issue payloads
+
+Each directory is one issue, in the local order. Post them with the GitHub CLI:
+
+```sh
+number=$(gh api repos/OWNER/REPO/issues --input 0001-title/issue.json --jq .number)
+for c in 0001-title/comments/*.json; do
+  gh api repos/OWNER/REPO/issues/$number/comments --input \"$c\"
+done
+# only closed issues have close.json
+gh api -X PATCH repos/OWNER/REPO/issues/$number --input 0001-title/close.json
+```
+
+Labels that don't exist in the repository are created automatically.
+";
+
+fn json_file(path: String, value: &Value) -> OutputFile {
+    OutputFile {
+        path,
+        contents: serde_json::to_string_pretty(value).unwrap_or_default() + "\n",
+    }
+}
+
+/// シェルの単一引用符で囲む
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// 本文に出てこない heredoc の終端
+fn heredoc_end(text: &str) -> String {
+    let mut end = "FORK_NOTES_EOF".to_string();
+    while text.lines().any(|line| line == end) {
+        end.push('_');
+    }
+    end
+}
+
+fn heredoc(text: &str) -> String {
+    let end = heredoc_end(text);
+    format!("<<'{}'\n{}\n{}\n", end, text, end)
+}
+
+/// `gh issue create` などを並べたシェルスクリプト。
+/// 作った Issue の URL を変数に入れておき、コメント・クローズ・fork の言及に使う。
+pub fn render_script(issues: &Issues, ids: &IssueIds, trash: &Trash) -> String {
+    let mut out = String::from(
+        "#!/bin/sh\n\
+         # Recreates the issues exported from fork_notes with the GitHub CLI.\n\
+         # Run it inside a clone of the target repository, or set GH_REPO=owner/repo.\n\
+         set -e\n\n",
+    );
+
+    let kept = trash.kept_issues(issues, ids);
+    let mut labels: Vec<String> = kept
+        .iter()
+        .flat_map(|&i| issues.get_list()[i].get_labels())
+        .collect();
+    labels.sort();
+    labels.dedup();
+    for label in labels {
+        out.push_str(&format!(
+            "gh label create {} --force\n",
+            shell_quote(&label)
+        ));
+    }
+
+    for &i in &kept {
+        let issue = &issues.get_list()[i];
+        let var = format!("issue_{}", ids.number(i));
+        out.push_str(&format!(
+            "\n# #{} {}\n",
+            ids.number(i),
+            issue.name().replace('\n', " ")
+        ));
+        let labels: String = issue
+            .get_labels()
+            .iter()
+            .map(|l| format!(" --label {}", shell_quote(l)))
+            .collect();
+        out.push_str(&format!(
+            "{}=$(gh issue create --title {}{} --body-file - {})\n",
+            var,
+            shell_quote(issue.name()),
+            labels,
+            heredoc(&issue_body(issues, ids, trash, i, issue))
+        ));
+        for comment in trash.kept_comments(ids, i, issue).into_iter().skip(1) {
+            out.push_str(&format!(
+                "gh issue comment \"${}\" --body-file - {}",
+                var,
+                heredoc(&attributed(comment))
+            ));
+        }
+    }
+
+    // 全て作ってから、URL が分かった状態で fork の関係を書き添えて閉じる
+    out.push_str("\n# Forks and close reasons\n");
+    for &i in &kept {
+        let issue = &issues.get_list()[i];
+        if let Some(parent) = trash.kept_parent(issues, ids, i) {
+            out.push_str(&format!(
+                "gh issue comment \"$issue_{}\" --body \"Forked from $issue_{}\"\n",
+                ids.number(i),
+                ids.number(parent)
+            ));
+        }
+        if let Some((_, reason)) = close_reason(issue.status()) {
+            out.push_str(&format!(
+                "gh issue close \"$issue_{}\" --reason {}\n",
+                ids.number(i),
+                shell_quote(reason)
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeNotesApp;
+    use crate::fixtures;
+    use crate::journal::Op;
+
+    const ISSUES: &str = r#"[
+        {"number": 2, "title": "second", "body": null, "state": "closed",
+         "state_reason": "not_planned", "labels": [{"name": "bug"}],
+         "user": {"login": "b"}, "created_at": "2024-01-02T00:00:00Z"},
+        {"number": 1, "title": "first", "body": "body", "state": "open",
+         "state_reason": null, "labels": [], "user": null,
+         "created_at": "2024-01-01T00:00:00Z"},
+        {"number": 3, "title": "a pull request", "body": "", "state": "open",
+         "state_reason": null, "user": {"login": "a"},
+         "created_at": "2024-01-03T00:00:00Z", "pull_request": {}}
+    ]"#;
+
+    const COMMENTS: &str = r#"[
+        {"issue_url": "https://api.github.com/repos/o/r/issues/1",
+         "user": {"login": "a"}, "body": "reply", "created_at": "2024-01-04T00:00:00Z"}
+    ]"#;
+
+    #[test]
+    fn convert_orders_issues_and_attaches_comments() {
+        let files = [
+            ("issues.json".to_string(), ISSUES.to_string()),
+            ("comments.json".to_string(), COMMENTS.to_string()),
+        ];
+        let (issues, users) = convert(&files).unwrap();
+        let list = issues.get_list();
+        // root と、Pull Request を除いた2件
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[1].name(), "first");
+        assert_eq!(list[1].created_by().name(), GHOST);
+        let texts: Vec<String> = list[1].comments().iter().map(|c| c.text()).collect();
+        assert_eq!(texts, ["body", "reply"]);
+        assert_eq!(list[2].status(), &Status::CloseAsNotPlaned);
+        assert_eq!(list[2].get_labels(), ["bug"]);
+        assert!(users.find("b").is_some());
+    }
+
+    #[test]
+    fn payloads_leave_out_deleted_issues() {
+        let mut app = fixtures::app_with_issues(&["kept", "deleted"]);
+        Op::Delete {
+            issue: app.ids.key(2),
+            comment: None,
+            by: app.current_user.clone(),
+            date: Local::now(),
+        }
+        .apply(&mut app);
+        let TreeNotesApp {
+            issues, ids, trash, ..
+        } = &app;
+
+        let files = render_payloads(issues, ids, trash);
+        let issue_files: Vec<&str> = files
+            .iter()
+            .map(|f| f.path.as_str())
+            .filter(|path| path.ends_with("issue.json"))
+            .collect();
+        assert_eq!(issue_files, ["0002-kept/issue.json"]);
+        assert!(!render_script(issues, ids, trash).contains("deleted"));
+    }
+}
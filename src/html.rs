@@ -0,0 +1,285 @@
+// ----------------------------------------------------------------------------
+// 静的 HTML サイトのエクスポート
+// ----------------------------------------------------------------------------
+//
+// 読み取り専用のスナップショットとして、外部ファイルに依存しないディレクトリを書き出す。
+//
+// - `index.html`: Issue の一覧。状態での絞り込みはページ内の小さなスクリプトで行う
+// - `issues/0003-title.html`: Issue ごとのページ。コメントと fork 元・fork 先へのリンク
+
+use local_issues_lib::{Issue, Issues, Status};
+
+use crate::export::{self, OutputFile};
+use crate::ids::IssueIds;
+use crate::labels::{self, Labels};
+use crate::merge::status_label;
+use crate::trash::Trash;
+
+const STYLE: &str = "
+body { font-family: sans-serif; max-width: 860px; margin: 2em auto; padding: 0 1em; color: #1f2328; }
+a { color: #0969da; text-decoration: none; }
+a:hover { text-decoration: underline; }
+.status { color: #fff; padding: 0.1em 0.6em; border-radius: 1em; font-size: 0.85em; font-weight: bold; }
+.open { background: #2ea043; }
+.completed { background: #8250df; }
+.not-planned { background: #808080; }
+.forked { background: #0000ff; }
+.label { padding: 0.1em 0.5em; border-radius: 1em; font-size: 0.85em; }
+.meta { color: #59636e; }
+.filters button { margin-right: 0.3em; }
+.filters button.active { font-weight: bold; }
+ul.issues { list-style: none; padding: 0; }
+ul.issues li { padding: 0.5em 0; border-bottom: 1px solid #d1d9e0; }
+.comment { border: 1px solid #d1d9e0; border-radius: 6px; margin: 1em 0; }
+.comment header { background: #f6f8fa; padding: 0.5em 1em; border-bottom: 1px solid #d1d9e0; }
+.comment .body { padding: 0.5em 1em; white-space: pre-wrap; }
+";
+
+/// 一覧の絞り込み。`data-filter` と各行の `data-status` を見て表示を切り替える。
+/// Forked は Completed に含める (サイドパネルと同じ)。
+const FILTER_SCRIPT: &str = "
+document.querySelectorAll('.filters button').forEach(function (button) {
+  button.addEventListener('click', function () {
+    var filter = button.dataset.filter;
+    document.querySelectorAll('.filters button').forEach(function (b) {
+      b.classList.toggle('active', b === button);
+    });
+    document.querySelectorAll('ul.issues li').forEach(function (li) {
+      var status = li.dataset.status;
+      var show = filter === 'all' || status === filter
+        || (filter === 'completed' && status === 'forked');
+      li.style.display = show ? '' : 'none';
+    });
+  });
+});
+";
+
+/// `<`, `>`, `&`, `"` をエスケープする
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// CSS のクラス名と、一覧の絞り込みに使う値
+fn status_class(status: &Status) -> &'static str {
+    match status {
+        Status::Open => "open",
+        Status::CloseAsCmp => "completed",
+        Status::CloseAsNotPlaned => "not-planned",
+        Status::CloseAsForked => "forked",
+    }
+}
+
+fn status_badge(status: &Status) -> String {
+    format!(
+        "<span class=\"status {}\">{}</span>",
+        status_class(status),
+        status_label(status)
+    )
+}
+
+/// 登録された色 (無ければ灰色) のラベル。説明があればホバーで出す (画面と同じ)。
+fn label_chip(labels: &Labels, name: &str) -> String {
+    let color = labels.color(name);
+    let [r, g, b] = color;
+    let title = match labels.get(name) {
+        Some(label) if !label.description.is_empty() => {
+            format!(" title=\"{}\"", escape(&label.description))
+        }
+        _ => String::new(),
+    };
+    format!(
+        "<span class=\"label\" style=\"background: #{:02x}{:02x}{:02x}; color: {}\"{}>{}</span>",
+        r,
+        g,
+        b,
+        if labels::is_light(color) {
+            "#000"
+        } else {
+            "#fff"
+        },
+        title,
+        escape(name)
+    )
+}
+
+fn page_name(number: usize, issue: &Issue) -> String {
+    format!("{:04}-{}.html", number, export::slug(issue.name()))
+}
+
+/// Issue ページから見た他の Issue へのリンク
+fn issue_link(issues: &Issues, ids: &IssueIds, index: usize) -> String {
+    let number = ids.number(index);
+    match issues.get(index) {
+        Some(issue) => format!(
+            "<a href=\"{}\">#{} {}</a>",
+            page_name(number, issue),
+            number,
+            escape(issue.name())
+        ),
+        None => format!("#{}", number),
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+fn render_index(issues: &Issues, ids: &IssueIds, trash: &Trash) -> String {
+    let mut body = String::from("<h1>Tree Notes</h1>\n<div class=\"filters\">\n");
+    for (filter, text) in [
+        ("open", "Open"),
+        ("completed", "Completed"),
+        ("not-planned", "Not Planned"),
+        ("all", "All"),
+    ] {
+        body.push_str(&format!(
+            "<button data-filter=\"{}\"{}>{}</button>\n",
+            filter,
+            if filter == "all" {
+                " class=\"active\""
+            } else {
+                ""
+            },
+            text
+        ));
+    }
+    body.push_str("</div>\n<ul class=\"issues\">\n");
+
+    // サイドパネルと同じく新しい順
+    for i in trash.kept_issues(issues, ids).into_iter().rev() {
+        let issue = &issues.get_list()[i];
+        body.push_str(&format!(
+            "<li data-status=\"{}\">{} <a href=\"issues/{}\">#{} {}</a> \
+             <span class=\"meta\">{} · {} comments</span></li>\n",
+            status_class(issue.status()),
+            status_badge(issue.status()),
+            page_name(ids.number(i), issue),
+            ids.number(i),
+            escape(issue.name()),
+            issue.created_at().format("%Y-%m-%d %H:%M"),
+            trash.kept_comments(ids, i, issue).len()
+        ));
+    }
+    body.push_str("</ul>\n");
+    body.push_str(&format!("<script>{}</script>\n", FILTER_SCRIPT));
+    page("Tree Notes", &body)
+}
+
+fn render_issue(
+    issues: &Issues,
+    ids: &IssueIds,
+    trash: &Trash,
+    labels: &Labels,
+    index: usize,
+    issue: &Issue,
+) -> String {
+    let mut body = String::from("<p><a href=\"../index.html\">← All issues</a></p>\n");
+    body.push_str(&format!(
+        "<h1>{} <span class=\"meta\">#{}</span></h1>\n<p>{} <span class=\"meta\">{}</span>",
+        escape(issue.name()),
+        ids.number(index),
+        status_badge(issue.status()),
+        issue.created_at().format("%Y-%m-%d %H:%M")
+    ));
+    for label in issue.get_labels() {
+        body.push_str(&format!(" {}", label_chip(labels, &label)));
+    }
+    body.push_str("</p>\n");
+
+    if let Some(parent) = trash.kept_parent(issues, ids, index) {
+        body.push_str(&format!(
+            "<p>Forked from {}</p>\n",
+            issue_link(issues, ids, parent)
+        ));
+    }
+    let forks: Vec<String> = trash
+        .kept_children(issues, ids, index)
+        .into_iter()
+        .map(|i| issue_link(issues, ids, i))
+        .collect();
+    if !forks.is_empty() {
+        body.push_str(&format!("<p>Forks: {}</p>\n", forks.join(", ")));
+    }
+
+    for comment in trash.kept_comments(ids, index, issue) {
+        body.push_str(&format!(
+            "<div class=\"comment\">\n<header><strong>{}</strong> \
+             <span class=\"meta\">{}</span></header>\n<div class=\"body\">{}</div>\n</div>\n",
+            escape(&comment.author().name()),
+            comment.date().format("%Y-%m-%d %H:%M"),
+            escape(comment.text().trim_end())
+        ));
+    }
+    page(&format!("#{} {}", ids.number(index), issue.name()), &body)
+}
+
+/// `index.html` と `issues/` 以下の Issue ごとのページ。ゴミ箱に入っているものは書き出さない。
+pub fn render_site(
+    issues: &Issues,
+    ids: &IssueIds,
+    trash: &Trash,
+    labels: &Labels,
+) -> Vec<OutputFile> {
+    let mut files = vec![OutputFile {
+        path: "index.html".to_string(),
+        contents: render_index(issues, ids, trash),
+    }];
+    for i in trash.kept_issues(issues, ids) {
+        let issue = &issues.get_list()[i];
+        files.push(OutputFile {
+            path: format!("issues/{}", page_name(ids.number(i), issue)),
+            contents: render_issue(issues, ids, trash, labels, i, issue),
+        });
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeNotesApp;
+    use crate::fixtures;
+
+    #[test]
+    fn labels_use_their_registered_colors() {
+        let mut app = TreeNotesApp::default();
+        let issue = fixtures::issue(&app, "tagged", &["bug", "someday"]);
+        let index = fixtures::add(&mut app, issue);
+        let mut bug = app.labels.new_label("bug");
+        bug.color = [215, 58, 74];
+        bug.description = "Something is wrong".to_string();
+        app.labels.define(bug);
+
+        let page = render_issue(
+            &app.issues,
+            &app.ids,
+            &app.trash,
+            &app.labels,
+            index,
+            &app.issues.get_list()[index],
+        );
+        assert!(page.contains(
+            "<span class=\"label\" style=\"background: #d73a4a; color: #fff\" \
+             title=\"Something is wrong\">bug</span>"
+        ));
+        // 登録されていないラベルは灰色
+        assert!(page.contains("style=\"background: #d3d3d3; color: #000\">someday</span>"));
+    }
+}
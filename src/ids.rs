@@ -0,0 +1,160 @@
+// ----------------------------------------------------------------------------
+// Issue の番号と UUID
+// ----------------------------------------------------------------------------
+//
+// ライブラリの Issue は `Issues` の中の位置でしか指せないので、ここで Issue ごとに
+//
+// - 番号: 画面やエクスポートに出す `#番号`。作った順に振り、消しても使い回さない
+// - UUID: 別のマシンで作ったデータを取り込むときに同じ Issue かを判断する
+// - fork 元の UUID: 取り込みで位置が変わっても fork の関係を保つ
+//
+// を持つ。`list` は `Issues` と同じ順に並ぶ。取り込みで位置が変わっても指す先がずれないように、
+// ジャーナルの操作・ゴミ箱・編集履歴・担当者は Issue を UUID で指す。
+
+use local_issues_lib::Issues;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::merge::has_parent;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct IssueId {
+    pub number: usize,
+    pub uuid: String,
+    /// fork 元の UUID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+/// ジャーナルの操作が指す Issue。UUID を持つ前に書かれた操作は位置で指している。
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum IssueRef {
+    Uuid(String),
+    Index(usize),
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct IssueIds {
+    list: Vec<IssueId>,
+}
+
+impl IssueIds {
+    fn next_number(&self) -> usize {
+        self.list.iter().map(|id| id.number).max().unwrap_or(0) + 1
+    }
+
+    /// 末尾に追加された Issue に番号を振る。`uuid` が無ければ新しく作る。
+    /// `parent` は fork 元の UUID。
+    pub fn push(&mut self, uuid: Option<String>, parent: Option<String>) {
+        self.list.push(IssueId {
+            number: self.next_number(),
+            uuid: uuid.unwrap_or_else(new_uuid),
+            parent,
+        });
+    }
+
+    /// まだ番号の無い Issue (番号を持つ前のデータや他の形式からの取り込み) に番号を振る
+    pub fn sync(&mut self, issues: &Issues) {
+        let list = issues.get_list();
+        self.list.truncate(list.len());
+        for issue in &list[self.list.len()..] {
+            let parent = has_parent(issue)
+                .then(|| self.uuid(issue.from_index()).map(String::from))
+                .flatten();
+            self.push(None, parent);
+        }
+    }
+
+    /// 表示用の番号。番号が無ければ位置から決める。
+    pub fn number(&self, index: usize) -> usize {
+        self.list
+            .get(index)
+            .map(|id| id.number)
+            .unwrap_or(index + 1)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&IssueId> {
+        self.list.get(index)
+    }
+
+    pub fn uuid(&self, index: usize) -> Option<&str> {
+        self.list.get(index).map(|id| id.uuid.as_str())
+    }
+
+    /// 操作で `index` を指すときの参照。UUID がまだ無ければ位置で指す。
+    pub fn key(&self, index: usize) -> IssueRef {
+        match self.uuid(index) {
+            Some(uuid) => IssueRef::Uuid(uuid.to_string()),
+            None => IssueRef::Index(index),
+        }
+    }
+
+    /// 参照している Issue の位置
+    pub fn resolve(&self, issue: &IssueRef) -> Option<usize> {
+        match issue {
+            IssueRef::Uuid(uuid) => self.index_of_uuid(uuid),
+            IssueRef::Index(index) => Some(*index),
+        }
+    }
+
+    pub fn index_of(&self, number: usize) -> Option<usize> {
+        self.list.iter().position(|id| id.number == number)
+    }
+
+    pub fn index_of_uuid(&self, uuid: &str) -> Option<usize> {
+        self.list.iter().position(|id| id.uuid == uuid)
+    }
+
+    /// fork 元の位置。UUID で記録されていればそれを、無ければ `from_index` を使う。
+    pub fn parent(&self, issues: &Issues, index: usize) -> Option<usize> {
+        if let Some(uuid) = self.list.get(index).and_then(|id| id.parent.as_deref()) {
+            return self.index_of_uuid(uuid);
+        }
+        let issue = issues.get(index)?;
+        (has_parent(issue) && issues.get(issue.from_index()).is_some()).then(|| issue.from_index())
+    }
+
+    /// `index` から fork した Issue の位置
+    pub fn children(&self, issues: &Issues, index: usize) -> Vec<usize> {
+        (0..issues.get_list().len())
+            .filter(|&i| self.parent(issues, i) == Some(index))
+            .collect()
+    }
+}
+
+/// ランダムな UUID (version 4)。
+/// 乱数用の crate は使わず、標準ライブラリの `RandomState` (プロセスごとにランダムな鍵を持つ)
+/// に時刻と通し番号を混ぜて作る。
+pub fn new_uuid() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let now = chrono::Local::now()
+        .timestamp_nanos_opt()
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    let state = RandomState::new();
+    let mut bytes = [0u8; 16];
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_i64(now);
+        hasher.write_u64(count);
+        hasher.write_usize(i);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
@@ -0,0 +1,603 @@
+// ----------------------------------------------------------------------------
+// 操作ジャーナル
+// ----------------------------------------------------------------------------
+//
+// UI からの変更は全て `Op` として保存先のジャーナルに追記してから `Issues` / `Users` / `Labels` /
+// `Revisions` / `Trash` / `IssueIds` / `Assignees` に適用する。
+// 保存したデータには最後に適用した `seq` を持たせておき、起動時にそれより後の操作をやり直す。
+// ジャーナル自体は消さないので、誰がいつ何をしたかの記録にもなる。
+//
+// ファイルに置く場合は1行1件の JSON (JSON Lines)。
+
+use chrono::{DateTime, Local};
+use local_issues_lib::{Comment, Issue, user::User};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::TreeNotesApp;
+use crate::ids::{IssueIds, IssueRef};
+use crate::labels::LabelDef;
+use crate::merge::{has_parent, with_field};
+use crate::revisions::Revision;
+use crate::trash::Deleted;
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Op {
+    AddNewIssue {
+        issue: Issue,
+        /// 取り込みなどで UUID が決まっている場合。無ければ適用時に作る。
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uuid: Option<String>,
+        /// fork 元の UUID。無ければ `from_index` から決める。
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent: Option<String>,
+    },
+    Comment {
+        issue: IssueRef,
+        comment: Comment,
+    },
+    CloseAsCmp {
+        issue: IssueRef,
+    },
+    CloseAsNotPlaned {
+        issue: IssueRef,
+    },
+    Reopen {
+        issue: IssueRef,
+    },
+    Fork {
+        issue: IssueRef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uuid: Option<String>,
+    },
+    AddUser {
+        user: User,
+    },
+    SetLabels {
+        issue: IssueRef,
+        labels: Vec<String>,
+    },
+    /// 登録簿に追加する。同じ名前があれば色と説明を置き換える。
+    DefineLabel {
+        label: LabelDef,
+    },
+    /// 登録簿から消し、全ての Issue から外す
+    RemoveLabel {
+        name: String,
+    },
+    /// タイトルを書き換え、前のタイトルを履歴に残す
+    EditTitle {
+        issue: IssueRef,
+        title: String,
+        editor: User,
+        date: DateTime<Local>,
+    },
+    /// コメントの本文を書き換え、前の本文を履歴に残す
+    EditComment {
+        issue: IssueRef,
+        comment: usize,
+        text: String,
+        editor: User,
+        date: DateTime<Local>,
+    },
+    /// 担当者をユーザー名で置き換える。空なら担当者無し。
+    SetAssignees {
+        issue: IssueRef,
+        assignees: Vec<String>,
+    },
+    /// ゴミ箱に入れる。`comment` が None なら Issue そのもの。
+    Delete {
+        issue: IssueRef,
+        comment: Option<usize>,
+        by: User,
+        date: DateTime<Local>,
+    },
+    /// ゴミ箱から元に戻す
+    Restore {
+        issue: IssueRef,
+        comment: Option<usize>,
+    },
+    /// ゴミ箱から完全に削除する。位置は残して本文と編集履歴を消す。
+    Purge {
+        issue: IssueRef,
+        comment: Option<usize>,
+    },
+    /// 取り込んだ編集履歴を足す。既にある編集は足さない。
+    AddRevisions {
+        issue: IssueRef,
+        comment: Option<usize>,
+        revisions: Vec<Revision>,
+    },
+    /// `seqs` の操作を取り消した印。状態は undo.rs で戻すので、適用しても何もしない。
+    Undo {
+        seqs: Vec<u64>,
+        /// 取り消した操作の説明
+        label: String,
+    },
+}
+
+impl Op {
+    /// 適用する。Issue が増えた場合はその index を返す。
+    pub fn apply(self, app: &mut TreeNotesApp) -> Option<usize> {
+        let TreeNotesApp {
+            issues,
+            users,
+            labels,
+            revisions,
+            trash,
+            ids,
+            assignees,
+            ..
+        } = app;
+        ids.sync(issues);
+        match self {
+            Op::AddNewIssue {
+                issue,
+                uuid,
+                parent,
+            } => {
+                let parent = parent.or_else(|| {
+                    has_parent(&issue)
+                        .then(|| ids.uuid(issue.from_index()).map(String::from))
+                        .flatten()
+                });
+                let index = issues.add_new_issue(issue);
+                ids.push(uuid, parent);
+                Some(index)
+            }
+            Op::Comment { issue, comment } => {
+                issues.get_mut(ids.resolve(&issue)?)?.comment(comment);
+                None
+            }
+            Op::CloseAsCmp { issue } => {
+                issues.get_mut(ids.resolve(&issue)?)?.close_as_cmp();
+                None
+            }
+            Op::CloseAsNotPlaned { issue } => {
+                issues.get_mut(ids.resolve(&issue)?)?.close_as_not_planed();
+                None
+            }
+            Op::Reopen { issue } => {
+                issues.get_mut(ids.resolve(&issue)?)?.reopen();
+                None
+            }
+            Op::Fork { issue, uuid } => {
+                let issue = ids.resolve(&issue)?;
+                let parent = ids.uuid(issue).map(String::from);
+                let index = issues.fork(issue)?;
+                ids.push(uuid, parent);
+                Some(index)
+            }
+            Op::AddUser { user } => {
+                users.add_user(user);
+                None
+            }
+            Op::SetLabels { issue, labels } => {
+                let target = issues.get_mut(ids.resolve(&issue)?)?;
+                *target = rewritten(with_labels(target, labels))?;
+                None
+            }
+            Op::DefineLabel { label } => {
+                labels.define(label);
+                None
+            }
+            Op::RemoveLabel { name } => {
+                labels.remove(&name);
+                for i in 0..issues.get_list().len() {
+                    let Some(issue) = issues.get_mut(i) else {
+                        continue;
+                    };
+                    if issue.get_labels().contains(&name) {
+                        let rest = issue
+                            .get_labels()
+                            .iter()
+                            .filter(|l| **l != name)
+                            .cloned()
+                            .collect();
+                        if let Some(updated) = rewritten(with_labels(issue, rest)) {
+                            *issue = updated;
+                        }
+                    }
+                }
+                None
+            }
+            Op::EditTitle {
+                issue,
+                title,
+                editor,
+                date,
+            } => {
+                let index = ids.resolve(&issue)?;
+                let target = issues.get_mut(index)?;
+                if target.name() == title {
+                    return None;
+                }
+                let updated = rewritten(with_field(target, "name", Value::from(title)))?;
+                revisions.record(
+                    ids.uuid(index)?,
+                    None,
+                    Revision {
+                        before: target.name().to_string(),
+                        editor,
+                        date,
+                    },
+                );
+                *target = updated;
+                None
+            }
+            Op::EditComment {
+                issue,
+                comment,
+                text,
+                editor,
+                date,
+            } => {
+                let index = ids.resolve(&issue)?;
+                let target = issues.get_mut(index)?;
+                let before = target.comments().get(comment)?.text();
+                if before == text {
+                    return None;
+                }
+                let updated = rewritten(with_comment_text(target, comment, text))?;
+                revisions.record(
+                    ids.uuid(index)?,
+                    Some(comment),
+                    Revision {
+                        before,
+                        editor,
+                        date,
+                    },
+                );
+                *target = updated;
+                None
+            }
+            Op::SetAssignees {
+                issue,
+                assignees: names,
+            } => {
+                let uuid = ids.uuid(ids.resolve(&issue)?)?;
+                assignees.set(uuid, names);
+                None
+            }
+            Op::Delete {
+                issue,
+                comment,
+                by,
+                date,
+            } => {
+                let index = ids.resolve(&issue)?;
+                let target = issues.get(index)?;
+                if comment.is_none_or(|c| c < target.comments().len()) {
+                    trash.delete(Deleted {
+                        issue: ids.uuid(index)?.to_string(),
+                        comment,
+                        by,
+                        date,
+                    });
+                }
+                None
+            }
+            Op::Restore { issue, comment } => {
+                trash.restore(ids.uuid(ids.resolve(&issue)?)?, comment);
+                None
+            }
+            Op::Purge { issue, comment } => {
+                let index = ids.resolve(&issue)?;
+                let uuid = ids.uuid(index)?;
+                // ゴミ箱に入っていないものは消さない
+                trash.get(uuid, comment)?;
+                let target = issues.get_mut(index)?;
+                let purged = match comment {
+                    Some(comment) => with_comment_text(target, comment, String::new()),
+                    None => with_field(target, "name", Value::from(""))
+                        .and_then(|issue| with_labels(&issue, Vec::new()))
+                        .and_then(|issue| with_field(&issue, "comment", Value::Array(Vec::new()))),
+                };
+                *target = rewritten(purged)?;
+                trash.purge(uuid, comment);
+                match comment {
+                    Some(comment) => revisions.remove_comment(uuid, comment),
+                    None => revisions.remove_issue(uuid),
+                }
+                None
+            }
+            Op::AddRevisions {
+                issue,
+                comment,
+                revisions: added,
+            } => {
+                let uuid = ids.uuid(ids.resolve(&issue)?)?;
+                for revision in added {
+                    let exists = revisions.get(uuid, comment).iter().any(|r| {
+                        r.editor.name() == revision.editor.name()
+                            && r.date == revision.date
+                            && r.before == revision.before
+                    });
+                    if !exists {
+                        revisions.record(uuid, comment, revision);
+                    }
+                }
+                None
+            }
+            Op::Undo { .. } => None,
+        }
+    }
+
+    /// 履歴に表示する説明
+    pub fn describe(&self, ids: &IssueIds) -> String {
+        match self {
+            Op::AddNewIssue { issue, .. } => format!("created \"{}\"", issue.name()),
+            Op::Comment { issue, .. } => format!("commented on #{}", number(ids, issue)),
+            Op::CloseAsCmp { issue } => format!("closed #{} as completed", number(ids, issue)),
+            Op::CloseAsNotPlaned { issue } => {
+                format!("closed #{} as not planned", number(ids, issue))
+            }
+            Op::Reopen { issue } => format!("reopened #{}", number(ids, issue)),
+            Op::Fork { issue, .. } => format!("forked #{}", number(ids, issue)),
+            Op::AddUser { user } => format!("added user {}", user.name()),
+            Op::SetLabels { issue, labels } if labels.is_empty() => {
+                format!("removed all labels from #{}", number(ids, issue))
+            }
+            Op::SetLabels { issue, labels } => {
+                format!("labeled #{} {}", number(ids, issue), labels.join(", "))
+            }
+            Op::DefineLabel { label } => format!("defined label {}", label.name),
+            Op::RemoveLabel { name } => format!("deleted label {}", name),
+            Op::EditTitle { issue, title, .. } => {
+                format!("renamed #{} to \"{}\"", number(ids, issue), title)
+            }
+            Op::EditComment { issue, .. } => format!("edited a comment on #{}", number(ids, issue)),
+            Op::SetAssignees { issue, assignees } if assignees.is_empty() => {
+                format!("unassigned #{}", number(ids, issue))
+            }
+            Op::SetAssignees { issue, assignees } => {
+                format!(
+                    "assigned #{} to {}",
+                    number(ids, issue),
+                    assignees.join(", ")
+                )
+            }
+            Op::Delete {
+                issue,
+                comment: None,
+                ..
+            } => format!("deleted #{}", number(ids, issue)),
+            Op::Delete { issue, .. } => format!("deleted a comment on #{}", number(ids, issue)),
+            Op::Restore {
+                issue,
+                comment: None,
+            } => format!("restored #{}", number(ids, issue)),
+            Op::Restore { issue, .. } => format!("restored a comment on #{}", number(ids, issue)),
+            Op::Purge {
+                issue,
+                comment: None,
+            } => format!("permanently deleted #{}", number(ids, issue)),
+            Op::Purge { issue, .. } => {
+                format!("permanently deleted a comment on #{}", number(ids, issue))
+            }
+            Op::AddRevisions { issue, .. } => {
+                format!("imported the edit history of #{}", number(ids, issue))
+            }
+            Op::Undo { label, .. } => format!("undid \"{}\"", label),
+        }
+    }
+}
+
+/// 履歴に出す `#番号`。もう無い Issue なら "?"
+fn number(ids: &IssueIds, issue: &IssueRef) -> String {
+    ids.resolve(issue)
+        .map(|index| ids.number(index).to_string())
+        .unwrap_or_else(|| "?".to_string())
+}
+
+/// JSON を経由した書き換えに失敗した操作は記録して適用しない
+fn rewritten<T>(result: Result<T, String>) -> Option<T> {
+    result
+        .inspect_err(|e| log::error!("Failed to apply an operation: {}", e))
+        .ok()
+}
+
+/// ラベルを付け替えた Issue。ライブラリに変更用の API が無いので JSON を経由する。
+fn with_labels(issue: &Issue, labels: Vec<String>) -> Result<Issue, String> {
+    with_field(issue, "labels", Value::from(labels))
+}
+
+/// `index` 番目のコメントの本文を書き換えた Issue
+fn with_comment_text(issue: &Issue, index: usize, text: String) -> Result<Issue, String> {
+    let mut comments = Vec::new();
+    for (i, comment) in issue.comments().iter().enumerate() {
+        let comment = if i == index {
+            with_field(comment, "content", Value::from(text.clone()))?
+        } else {
+            comment.clone()
+        };
+        comments.push(serde_json::to_value(comment).map_err(|e| e.to_string())?);
+    }
+    with_field(issue, "comment", Value::from(comments))
+}
+
+/// `entries` の中で `Op::Undo` により取り消された操作の seq
+pub fn undone(entries: &[Entry]) -> HashSet<u64> {
+    entries
+        .iter()
+        .filter_map(|entry| match &entry.op {
+            Op::Undo { seqs, .. } => Some(seqs.iter().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Entry {
+    /// 1から始まる通し番号
+    pub seq: u64,
+    pub at: DateTime<Local>,
+    /// 操作したユーザーの名前
+    pub by: String,
+    #[serde(flatten)]
+    pub op: Op,
+}
+
+impl Entry {
+    pub fn new(seq: u64, by: &User, op: Op) -> Self {
+        Self {
+            seq,
+            at: Local::now(),
+            by: by.name().to_string(),
+            op,
+        }
+    }
+}
+
+/// JSON Lines を読む。書き込み途中で落ちた場合に備え、読めない行があればそこで打ち切る。
+pub fn parse_lines(content: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                log::warn!("Ignoring the journal after line {}: {}", i + 1, e);
+                break;
+            }
+        }
+    }
+    entries
+}
+
+/// トラッカーファイルの隣に置くジャーナル (`data.json` なら `data.journal.jsonl`)
+#[cfg(not(target_arch = "wasm32"))]
+pub fn journal_path(tracker: &Path) -> PathBuf {
+    let stem = tracker.file_stem().unwrap_or_default().to_string_lossy();
+    tracker.with_file_name(format!("{}.journal.jsonl", stem))
+}
+
+/// 1件追記して、ディスクに書かれるまで待つ
+#[cfg(not(target_arch = "wasm32"))]
+pub fn append(path: &Path, entry: &Entry) -> io::Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(line.as_bytes())?;
+    file.sync_data()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(parse_lines(&content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// 本文のコメントが1件ある Issue を1つ追加したアプリ
+    fn app_with_issue() -> (TreeNotesApp, usize) {
+        let app = fixtures::app_with_issues(&["title"]);
+        (app, 1)
+    }
+
+    #[test]
+    fn edit_comment_rewrites_text_and_keeps_the_old_one() {
+        let (mut app, index) = app_with_issue();
+        Op::EditComment {
+            issue: app.ids.key(index),
+            comment: 0,
+            text: "second".to_string(),
+            editor: app.current_user.clone(),
+            date: Local::now(),
+        }
+        .apply(&mut app);
+
+        let issue = app.issues.get(index).unwrap();
+        assert_eq!(issue.comments()[0].text(), "second");
+        let revisions = app.revisions.get(app.ids.uuid(index).unwrap(), Some(0));
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].before, "title");
+    }
+
+    #[test]
+    fn edit_title_rewrites_name() {
+        let (mut app, index) = app_with_issue();
+        Op::EditTitle {
+            issue: app.ids.key(index),
+            title: "renamed".to_string(),
+            editor: app.current_user.clone(),
+            date: Local::now(),
+        }
+        .apply(&mut app);
+
+        assert_eq!(app.issues.get(index).unwrap().name(), "renamed");
+        assert_eq!(
+            app.revisions.get(app.ids.uuid(index).unwrap(), None)[0].before,
+            "title"
+        );
+    }
+
+    #[test]
+    fn purge_blanks_the_comment_or_the_whole_issue() {
+        let (mut app, index) = app_with_issue();
+        let user = app.current_user.clone();
+        Op::Comment {
+            issue: app.ids.key(index),
+            comment: Comment::new("second", user.clone()),
+        }
+        .apply(&mut app);
+
+        // ゴミ箱に入れる前は消せない
+        Op::Purge {
+            issue: app.ids.key(index),
+            comment: Some(1),
+        }
+        .apply(&mut app);
+        assert_eq!(
+            app.issues.get(index).unwrap().comments()[1].text(),
+            "second"
+        );
+
+        for comment in [Some(1), None] {
+            Op::Delete {
+                issue: app.ids.key(index),
+                comment,
+                by: user.clone(),
+                date: Local::now(),
+            }
+            .apply(&mut app);
+            Op::Purge {
+                issue: app.ids.key(index),
+                comment,
+            }
+            .apply(&mut app);
+            if comment.is_some() {
+                assert_eq!(app.issues.get(index).unwrap().comments()[1].text(), "");
+            }
+        }
+        let issue = app.issues.get(index).unwrap();
+        assert_eq!(issue.name(), "");
+        assert!(issue.comments().is_empty());
+        assert!(app.trash.is_purged(app.ids.uuid(index).unwrap(), None));
+    }
+}
@@ -0,0 +1,105 @@
+// ----------------------------------------------------------------------------
+// ラベルの登録簿
+// ----------------------------------------------------------------------------
+//
+// Issue が持つのはラベルの名前だけなので、色と説明はここで名前ごとに管理する。
+// 登録されていない名前のラベルも表示はでき、その場合は既定の灰色になる。
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+/// 登録されていないラベルの色
+const DEFAULT_COLOR: [u8; 3] = [211, 211, 211];
+
+/// 新しいラベルに順番に割り当てる色
+const PALETTE: [[u8; 3]; 8] = [
+    [215, 58, 74],
+    [0, 117, 202],
+    [14, 138, 22],
+    [251, 202, 4],
+    [162, 238, 239],
+    [216, 118, 227],
+    [233, 109, 35],
+    [128, 128, 128],
+];
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct LabelDef {
+    pub name: String,
+    /// sRGB
+    pub color: [u8; 3],
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Labels {
+    list: Vec<LabelDef>,
+}
+
+impl Labels {
+    pub fn get_list(&self) -> &Vec<LabelDef> {
+        &self.list
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LabelDef> {
+        self.list.iter().find(|l| l.name == name)
+    }
+
+    /// 同じ名前があれば置き換え、無ければ追加する
+    pub fn define(&mut self, label: LabelDef) {
+        match self.list.iter_mut().find(|l| l.name == label.name) {
+            Some(existing) => *existing = label,
+            None => self.list.push(label),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.list.retain(|l| l.name != name);
+    }
+
+    /// 新しく作るラベルの初期値
+    pub fn new_label(&self, name: &str) -> LabelDef {
+        LabelDef {
+            name: name.to_string(),
+            color: PALETTE[self.list.len() % PALETTE.len()],
+            description: String::new(),
+        }
+    }
+
+    /// 登録されていなければ既定の灰色
+    pub fn color(&self, name: &str) -> [u8; 3] {
+        self.get(name).map(|l| l.color).unwrap_or(DEFAULT_COLOR)
+    }
+
+    /// 色付きのラベル表示。説明があればホバーで出す。
+    pub fn chip(&self, ui: &mut egui::Ui, name: &str) -> egui::Response {
+        let [r, g, b] = self.color(name);
+        let response = ui.add(egui::Label::new(
+            egui::RichText::new(format!(" {} ", name))
+                .color(text_color([r, g, b]))
+                .background_color(egui::Color32::from_rgb(r, g, b)),
+        ));
+        match self.get(name) {
+            Some(label) if !label.description.is_empty() => {
+                response.on_hover_text(&label.description)
+            }
+            _ => response,
+        }
+    }
+}
+
+/// 黒い文字が読める明るい色か
+pub fn is_light([r, g, b]: [u8; 3]) -> bool {
+    0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32 > 150.0
+}
+
+/// 背景色の上で読める文字色 (明るければ黒、暗ければ白)
+fn text_color(color: [u8; 3]) -> egui::Color32 {
+    if is_light(color) {
+        egui::Color32::BLACK
+    } else {
+        egui::Color32::WHITE
+    }
+}
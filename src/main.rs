@@ -12,10 +12,40 @@ use std::sync::mpsc::{Receiver, Sender, channel};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::JsCast;
 
+mod assignees;
+#[cfg(not(target_arch = "wasm32"))]
+mod backup;
+mod convert;
+mod csv;
+#[cfg(not(target_arch = "wasm32"))]
+mod dir_store;
+mod export;
+#[cfg(test)]
+mod fixtures;
+mod github;
+#[cfg(not(target_arch = "wasm32"))]
+mod html;
+mod ids;
+mod journal;
+mod labels;
+mod markdown;
+mod merge;
+mod revisions;
+mod schema;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+mod sqlite_store;
+mod storage;
+mod taskwarrior;
+mod todotxt;
+mod trash;
+mod undo;
+#[cfg(not(target_arch = "wasm32"))]
+mod workspace;
+
 // ----------------------------------------------------------------------------
 // 1. define filtering
 // ----------------------------------------------------------------------------
-#[derive(PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize)]
 enum FilterStatus {
     Open,
     Completed,  // "CloseAsCmp"
@@ -38,14 +68,141 @@ impl FilterStatus {
     }
 }
 
+/// 担当者と作成者での絞り込み
+#[derive(Default, PartialEq)]
+enum FilterPeople {
+    #[default]
+    Anyone,
+    AssignedToMe,
+    CreatedByMe,
+    Unassigned,
+}
+
+/// 起動時にデータを読み込めなかったときの状態
+struct LoadFailure {
+    message: String,
+    /// 壊れたデータの退避先
+    quarantined_to: Option<String>,
+    /// 新しいバージョンで保存されたファイルなど、中身が正常な場合は false。
+    /// その場合は上書きにつながる "Start Fresh" を出さない。
+    can_start_fresh: bool,
+}
+
+/// 最後の変更から保存するまでの秒数
+const SAVE_DELAY: f64 = 1.0;
+
+/// 取り込み用に選ばれたファイル
+struct ImportedFile {
+    name: String,
+    /// 読めなかった場合は位置を含めたエラーの説明
+    data: Result<ImportedData, String>,
+}
+
+enum ImportedData {
+    /// そのまま取り込めるトラッカー
+    Tracker(Box<TreeNotesApp>),
+    /// 他の形式から変換したもの。現在のユーザーなど個人の状態は持たない
+    Converted(Box<TreeNotesApp>),
+    /// どの列を何として読むかを選んでもらう必要がある
+    Csv(csv::Table),
+}
+
+impl ImportedData {
+    /// 他の形式から変換した Issue と User
+    fn converted(issues: Issues, users: Users) -> Self {
+        let mut app = TreeNotesApp {
+            issues,
+            users,
+            ..Default::default()
+        };
+        app.ids.sync(&app.issues);
+        ImportedData::Converted(Box::new(app))
+    }
+}
+
+impl ImportedFile {
+    /// 拡張子と中身で形式を判断する。それ以外はこのアプリの JSON として読む。
+    /// 作成者の情報を持たない形式は `author` が作ったことにする。
+    fn parse(name: String, bytes: &[u8], author: &User) -> Self {
+        let extension = name.rsplit('.').next().unwrap_or_default().to_lowercase();
+        let data = match std::str::from_utf8(bytes) {
+            Ok(text) if extension == "csv" => csv::parse(text).map(ImportedData::Csv),
+            Ok(text) if extension == "txt" => todotxt::convert(text, author)
+                .map(|(issues, users)| ImportedData::converted(issues, users)),
+            Ok(json) if taskwarrior::looks_like(json) => taskwarrior::convert(json, author)
+                .map(|(issues, users)| ImportedData::converted(issues, users)),
+            Ok(json) if github::looks_like(json) => {
+                github::convert(&[(name.clone(), json.to_string())])
+                    .map(|(issues, users)| ImportedData::converted(issues, users))
+            }
+            Ok(json) => schema::load(json)
+                .map(|app| ImportedData::Tracker(Box::new(app)))
+                .map_err(|e| schema::error_report(json, &e)),
+            Err(e) => Err(format!("the file is not UTF-8 text: {}", e)),
+        };
+        Self { name, data }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read(path: &std::path::Path, author: &User) -> Self {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        match std::fs::read(path) {
+            Ok(bytes) => Self::parse(name, &bytes, author),
+            Err(e) => Self {
+                name,
+                data: Err(format!("could not read file: {}", e)),
+            },
+        }
+    }
+}
+
+/// 取り込む前に確認してもらっているファイル
+struct PendingImport {
+    name: String,
+    preview: Result<ImportPreview, String>,
+}
+
+/// CSV の列の対応付けを選んでもらっているところ
+struct CsvImport {
+    name: String,
+    table: csv::Table,
+    mapping: csv::Mapping,
+    error: Option<String>,
+}
+
+struct ImportPreview {
+    data: Box<TreeNotesApp>,
+    /// 他の形式から変換したものか
+    converted: bool,
+    plan: merge::MergePlan,
+    diff: Vec<merge::DiffEntry>,
+}
+
 // ----------------------------------------------------------------------------
 // 2. アプリケーション構造体
 // ----------------------------------------------------------------------------
 #[derive(Deserialize, Serialize)]
 #[serde(default)]
 struct TreeNotesApp {
+    schema_version: u32,
     issues: Issues,
     users: Users,
+    /// ラベルの色と説明
+    labels: labels::Labels,
+    /// タイトルとコメントの編集履歴
+    revisions: revisions::Revisions,
+    /// 消した Issue とコメント
+    trash: trash::Trash,
+    /// Issue の番号と UUID (`issues` と同じ順)
+    ids: ids::IssueIds,
+    /// Issue の担当者
+    assignees: assignees::Assignees,
+    /// 最後に適用したジャーナルの seq。起動時はこれより後をやり直す。
+    journal_seq: u64,
 
     // UI State
     #[serde(skip)]
@@ -59,30 +216,115 @@ struct TreeNotesApp {
     #[serde(skip)]
     new_user_email: String,
 
+    // Label Manager UI State
+    #[serde(skip)]
+    show_label_manager: bool,
+    /// 追加・編集中のラベル
+    #[serde(skip)]
+    label_form: Option<labels::LabelDef>,
+    /// `label_form` が既存のラベルの編集か
+    #[serde(skip)]
+    editing_label: bool,
+    /// ラベルピッカーで新しく作るラベルの名前
+    #[serde(skip)]
+    new_label_name: String,
+
     // Navigation / Action State
+    /// Issue の番号ごとの書きかけのコメント
     #[serde(skip)]
     comment_drafts: HashMap<usize, String>,
+    /// 編集中のタイトル (Issue の番号, 本文)
+    #[serde(skip)]
+    editing_title: Option<(usize, String)>,
+    /// 編集中のコメント (Issue の番号, コメントの位置, 本文)
+    #[serde(skip)]
+    editing_comment: Option<(usize, usize, String)>,
     filter_status: FilterStatus,
     #[serde(skip)]
     query: String,
+    /// このラベルが付いた Issue だけを表示する
+    #[serde(skip)]
+    label_filter: Option<String>,
+    #[serde(skip)]
+    people_filter: FilterPeople,
 
-    // 選択中のIssue ID
+    // 選択中のIssueの番号
     #[serde(skip)]
-    selected_issue_index: Option<usize>,
+    selected_issue: Option<usize>,
 
     current_user: User,
 
     #[serde(skip)]
-    import_rx: Option<Receiver<TreeNotesApp>>,
+    import_rx: Option<Receiver<ImportedFile>>,
+    #[serde(skip)]
+    import_tx: Option<Sender<ImportedFile>>,
     #[serde(skip)]
-    import_tx: Option<Sender<TreeNotesApp>>,
+    pending_import: Option<PendingImport>,
+    #[serde(skip)]
+    csv_import: Option<CsvImport>,
     #[serde(skip)]
     show_about: bool,
 
+    /// 保存先
+    #[serde(skip)]
+    store: Box<dyn storage::Store>,
+
+    // Workspace State
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    workspaces: workspace::WorkspaceConfig,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    show_new_workspace: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    new_workspace_name: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    workspace_error: Option<String>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    open_path_rx: Option<Receiver<std::path::PathBuf>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    open_path_tx: Option<Sender<std::path::PathBuf>>,
+
+    #[serde(skip)]
+    undo: undo::UndoStack,
+
+    /// Export Markdown ウィンドウ。Some の間は開いている。
+    #[serde(skip)]
+    markdown_export: Option<markdown::Options>,
+
+    // History UI State
     #[serde(skip)]
-    last_auto_save: f64,
+    show_history: bool,
+    #[serde(skip)]
+    show_trash: bool,
+    #[serde(skip)]
+    history: Vec<journal::Entry>,
+
+    // Restore UI State
+    #[serde(skip)]
+    show_restore: bool,
+    #[serde(skip)]
+    backups: Vec<storage::SnapshotInfo>,
+    #[serde(skip)]
+    restore_error: Option<String>,
+
+    /// 保存していない変更がある
+    #[serde(skip)]
+    dirty: bool,
+    /// 最初に未保存の変更に気付いた時刻。ここから `SAVE_DELAY` 経ったら保存する。
+    #[serde(skip)]
+    dirty_since: Option<f64>,
     #[serde(skip)]
     last_save_time: f64,
+    #[serde(skip)]
+    last_save_error: Option<String>,
+    /// 起動時の読み込みに失敗した理由。Some の間は保存しない。
+    #[serde(skip)]
+    load_error: Option<LoadFailure>,
 }
 
 impl Default for TreeNotesApp {
@@ -95,80 +337,1068 @@ impl Default for TreeNotesApp {
         let mut issues = Issues::new();
 
         let (tx, rx) = channel();
+        #[cfg(not(target_arch = "wasm32"))]
+        let (open_path_tx, open_path_rx) = channel();
 
         Self {
+            schema_version: schema::CURRENT_VERSION,
             issues,
             users,
+            labels: labels::Labels::default(),
+            revisions: revisions::Revisions::default(),
+            trash: trash::Trash::default(),
+            ids: ids::IssueIds::default(),
+            assignees: assignees::Assignees::default(),
+            journal_seq: 0,
             new_description: String::new(),
             show_user_manager: false,
             new_user_name: String::new(),
             new_user_email: String::new(),
+            show_label_manager: false,
+            label_form: None,
+            editing_label: false,
+            new_label_name: String::new(),
             comment_drafts: HashMap::new(),
+            editing_title: None,
+            editing_comment: None,
             filter_status: FilterStatus::Open,
             query: String::new(),
-            selected_issue_index: None,
+            label_filter: None,
+            people_filter: FilterPeople::Anyone,
+            selected_issue: None,
             current_user,
             import_rx: Some(rx),
             import_tx: Some(tx),
+            pending_import: None,
+            csv_import: None,
             show_about: false,
-            last_auto_save: 0.0,
+            store: Box::new(storage::MemoryStore::new()),
+            #[cfg(not(target_arch = "wasm32"))]
+            workspaces: workspace::WorkspaceConfig::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            show_new_workspace: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            new_workspace_name: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            workspace_error: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            open_path_rx: Some(open_path_rx),
+            #[cfg(not(target_arch = "wasm32"))]
+            open_path_tx: Some(open_path_tx),
+            undo: undo::UndoStack::default(),
+            markdown_export: None,
+            show_history: false,
+            show_trash: false,
+            history: Vec::new(),
+            show_restore: false,
+            backups: Vec::new(),
+            restore_error: None,
+            dirty: false,
+            dirty_since: None,
             last_save_time: 0.0,
+            last_save_error: None,
+            load_error: None,
         }
     }
 }
 
 impl TreeNotesApp {
+    fn save_to_disk(&mut self) {
+        if self.load_error.is_some() {
+            // 読み込めなかったデータを空の状態で上書きしない
+            return;
+        }
+        // store は self の一部なので、保存している間だけ取り出しておく
+        let mut store = std::mem::replace(&mut self.store, Box::new(storage::MemoryStore::new()));
+        self.last_save_error = store.save(self).err();
+        self.store = store;
+        if self.last_save_error.is_none() {
+            self.dirty = false;
+            self.dirty_since = None;
+        }
+    }
+
+    /// 変更があった場合だけ、最後の変更から少し待って保存する。
+    /// 続けて操作している間の書き込みは1回にまとめる。ウィンドウを閉じるときは待たない。
+    fn save_if_dirty(&mut self, ctx: &egui::Context) {
+        if !self.dirty {
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        let since = *self.dirty_since.get_or_insert(now);
+        let closing = ctx.input(|i| i.viewport().close_requested());
+        if closing || now - since >= SAVE_DELAY {
+            self.save_to_disk();
+            self.last_save_time = now;
+            // 失敗した場合は少し待ってからやり直す
+            if self.dirty {
+                self.dirty_since = Some(now);
+            }
+        }
+        if self.dirty {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(SAVE_DELAY));
+        }
+    }
+
+    /// UI からの操作。やり直しの履歴はここで捨てる。
+    fn perform(&mut self, op: journal::Op) {
+        self.undo.clear_redo();
+        let label = op.describe(&self.ids);
+        self.apply_ops(label, vec![op]);
+    }
+
+    /// 複数の操作をまとめて1回の取り消し単位として適用する
+    fn perform_all(&mut self, label: String, ops: Vec<journal::Op>) {
+        self.undo.clear_redo();
+        self.apply_ops(label, ops);
+    }
+
+    /// ジャーナルに記録してから操作を適用し、取り消せるように操作を覚えておく。
+    /// 1つの操作で新しい Issue ができた場合はそれを選択する。
+    fn apply_ops(&mut self, label: String, ops: Vec<journal::Op>) {
+        if self.undo.needs_base() {
+            match undo::capture(self) {
+                Ok(base) => self.undo.set_base(base),
+                Err(e) => log::error!("Failed to record undo step: {}", e),
+            }
+        }
+
+        let select_new = ops.len() == 1;
+        let selected = self.selected_issue;
+        let mut seqs = Vec::new();
+        for op in ops.iter().cloned() {
+            let is_fork = matches!(op, journal::Op::Fork { .. });
+            let entry = journal::Entry::new(self.journal_seq + 1, &self.current_user, op);
+            // 記録できなくても操作自体は止めない (データの保存で残る)
+            if let Err(e) = self.store.append_journal(&entry) {
+                self.last_save_error = Some(format!("journal: {}", e));
+            }
+            self.journal_seq = entry.seq;
+            seqs.push(entry.seq);
+
+            if let Some(new_id) = entry.op.apply(self)
+                && select_new
+            {
+                if is_fork {
+                    self.filter_status = FilterStatus::All;
+                }
+                self.selected_issue = Some(self.ids.number(new_id));
+            }
+        }
+        self.undo.push(undo::Step {
+            label,
+            ops,
+            seqs,
+            selected,
+        });
+        self.dirty = true;
+    }
+
+    /// 直前の操作を取り消し、ジャーナルに `Op::Undo` を残す。
+    /// 取り消した状態を先に保存してから書くので、保存済みのデータに取り消した操作が
+    /// 残ったままジャーナルだけ先に進むことはない。
+    fn undo(&mut self) {
+        let (step, rebuilt) = match self.undo.undo() {
+            Some(Ok(undone)) => undone,
+            Some(Err(e)) => {
+                log::error!("Failed to undo: {}", e);
+                return;
+            }
+            None => return,
+        };
+        self.issues = rebuilt.issues;
+        self.users = rebuilt.users;
+        self.labels = rebuilt.labels;
+        self.revisions = rebuilt.revisions;
+        self.trash = rebuilt.trash;
+        self.ids = rebuilt.ids;
+        self.assignees = rebuilt.assignees;
+        self.selected_issue = step.selected;
+
+        let entry = journal::Entry::new(
+            self.journal_seq + 1,
+            &self.current_user,
+            journal::Op::Undo {
+                seqs: step.seqs.clone(),
+                label: step.label.clone(),
+            },
+        );
+        self.journal_seq = entry.seq;
+        self.save_to_disk();
+        if let Err(e) = self.store.append_journal(&entry) {
+            self.last_save_error = Some(format!("journal: {}", e));
+        }
+        self.undo.push_redo(step);
+    }
+
+    /// 取り消した操作をもう一度適用する
+    fn redo(&mut self) {
+        if let Some(step) = self.undo.pop_redo() {
+            self.apply_ops(step.label, step.ops);
+        }
+    }
+
+    /// 保存されたデータより後のジャーナルを適用する
+    fn replay_journal(&mut self) {
+        let entries = match self.store.read_journal() {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::error!("Failed to read the journal: {}", e);
+                return;
+            }
+        };
+        let saved_seq = self.journal_seq;
+        let mut pending = Vec::new();
+        for entry in entries.into_iter().filter(|e| e.seq > saved_seq) {
+            // 抜けがあるとそれ以降の index がずれるので止める
+            let expected = saved_seq + pending.len() as u64 + 1;
+            if entry.seq != expected {
+                log::warn!("Journal entry {} is missing", expected);
+                break;
+            }
+            pending.push(entry);
+        }
+        // 保存した後に取り消したものはやり直さない
+        let undone = journal::undone(&pending);
+        let mut replayed = 0;
+        for entry in pending {
+            self.journal_seq = entry.seq;
+            if undone.contains(&entry.seq) {
+                continue;
+            }
+            if let journal::Op::Undo { seqs, .. } = &entry.op
+                && seqs.iter().any(|&seq| seq <= saved_seq)
+            {
+                log::warn!("Entry {} undoes changes that were already saved", entry.seq);
+            }
+            entry.op.apply(self);
+            replayed += 1;
+        }
+        if replayed > 0 {
+            log::info!("Replayed {} journal entries", replayed);
+            self.dirty = true;
+        }
+    }
+
+    /// ジャーナルに書かれている最後の seq。
+    /// データを丸ごと置き換えた後、古い操作がやり直されないようにこれに合わせる。
+    fn journal_end(&self) -> u64 {
+        self.store
+            .read_journal()
+            .ok()
+            .and_then(|entries| entries.last().map(|e| e.seq))
+            .unwrap_or(0)
+            .max(self.journal_seq)
+    }
+
+    /// 選択中の Issue の index
+    fn selected_index(&self) -> Option<usize> {
+        self.selected_issue
+            .and_then(|number| self.ids.index_of(number))
+    }
+
+    /// サイドパネルに表示される Issue の index (新しい順)。root とゴミ箱のものは含めない。
+    fn filtered_indices(&self) -> Vec<usize> {
+        let query = self.query.to_lowercase();
+        let list = self.issues.get_list();
+        let mut indices: Vec<usize> = self
+            .trash
+            .kept_issues(&self.issues, &self.ids)
+            .into_iter()
+            .filter(|&i| self.filter_status.matches(list[i].status()))
+            .filter(|&i| query.is_empty() || list[i].name().to_lowercase().contains(&query))
+            .filter(|&i| {
+                self.label_filter
+                    .as_ref()
+                    .is_none_or(|label| list[i].get_labels().contains(label))
+            })
+            .filter(|&i| self.matches_people_filter(i, &list[i]))
+            .collect();
+        indices.reverse();
+        indices
+    }
+
+    /// `index` の担当者の名前
+    fn assignees_of(&self, index: usize) -> &[String] {
+        self.ids
+            .uuid(index)
+            .map(|uuid| self.assignees.get(uuid))
+            .unwrap_or_default()
+    }
+
+    fn matches_people_filter(&self, index: usize, issue: &Issue) -> bool {
+        let me = self.current_user.name();
+        match self.people_filter {
+            FilterPeople::Anyone => true,
+            FilterPeople::AssignedToMe => self.assignees_of(index).contains(&me),
+            FilterPeople::CreatedByMe => issue.created_by().name() == me,
+            FilterPeople::Unassigned => self.assignees_of(index).is_empty(),
+        }
+    }
+
+    /// 登録されているラベルと、登録されていないが使われているラベルの名前
+    fn label_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .labels
+            .get_list()
+            .iter()
+            .map(|l| l.name.clone())
+            .collect();
+        let mut unregistered: Vec<String> = self
+            .issues
+            .get_list()
+            .iter()
+            .flat_map(|issue| issue.get_labels())
+            .filter(|l| self.labels.get(l).is_none())
+            .collect();
+        unregistered.sort();
+        unregistered.dedup();
+        names.extend(unregistered);
+        names
+    }
+
+    fn show_markdown_export_window(&mut self, ctx: &egui::Context) {
+        if self.markdown_export.is_none() {
+            return;
+        }
+        let filtered_count = self.filtered_indices().len();
+        let issue_count = self.trash.kept_issues(&self.issues, &self.ids).len();
+        let Some(options) = &mut self.markdown_export else {
+            return;
+        };
+        let mut open = true;
+        let mut export = false;
+        egui::Window::new("Export Markdown")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.radio_value(
+                    &mut options.filtered_only,
+                    false,
+                    format!("All issues ({})", issue_count),
+                );
+                ui.radio_value(
+                    &mut options.filtered_only,
+                    true,
+                    format!("Filtered list ({})", filtered_count),
+                );
+                ui.separator();
+                ui.radio_value(&mut options.per_issue, false, "Single document");
+                // ブラウザからはフォルダに書き込めない
+                ui.add_enabled_ui(cfg!(not(target_arch = "wasm32")), |ui| {
+                    ui.radio_value(&mut options.per_issue, true, "One file per issue");
+                });
+                ui.separator();
+                if ui.button("Export").clicked() {
+                    export = true;
+                }
+            });
+
+        if export && let Some(options) = self.markdown_export.take() {
+            let indices = if options.filtered_only {
+                self.filtered_indices()
+            } else {
+                self.trash.kept_issues(&self.issues, &self.ids)
+            };
+            #[cfg(not(target_arch = "wasm32"))]
+            if options.per_issue {
+                export::save_to_folder(markdown::render_files(
+                    &self.issues,
+                    &self.ids,
+                    &self.trash,
+                    &indices,
+                ));
+                return;
+            }
+            export::save_file(
+                "tree_notes.md",
+                markdown::render_document(&self.issues, &self.ids, &self.trash, &indices),
+            );
+        } else if !open {
+            self.markdown_export = None;
+        }
+    }
+
+    fn refresh_history(&mut self) {
+        self.history = self.store.read_journal().unwrap_or_default();
+        self.history.reverse();
+    }
+
+    /// 現在の状態を保存した上でスナップショットを強制的に取る。
+    /// import や restore で中身を置き換える前に呼ぶ。
+    fn snapshot_before_replace(&mut self) {
+        self.save_to_disk();
+        if let Err(e) = self.store.create_snapshot() {
+            log::error!("{}", e);
+        }
+    }
+
+    fn replace_data(&mut self, new_app: TreeNotesApp) {
+        self.issues = new_app.issues;
+        self.users = new_app.users;
+        self.labels = new_app.labels;
+        self.revisions = new_app.revisions;
+        self.trash = new_app.trash;
+        self.label_filter = None;
+        self.current_user = new_app.current_user;
+        self.filter_status = new_app.filter_status;
+        self.ids = new_app.ids;
+        self.assignees = new_app.assignees;
+        self.people_filter = FilterPeople::Anyone;
+        self.selected_issue = None;
+        self.journal_seq = self.journal_end();
+        self.undo.clear();
+        self.dirty = true;
+    }
+
+    fn refresh_backups(&mut self) {
+        self.backups = self.store.list_snapshots();
+    }
+
+    /// 今のワークスペースを保存してから `path` のトラッカーに切り替える。
+    /// ファイルがまだ無ければ空のトラッカーとして作成する。
     #[cfg(not(target_arch = "wasm32"))]
-    fn get_data_path() -> Option<std::path::PathBuf> {
-        let data_dir = if let Ok(xdg) = std::env::var("XDG_DATA_HOME") {
-            std::path::PathBuf::from(xdg)
-        } else {
-            let home = std::env::var("HOME").ok()?;
-            std::path::PathBuf::from(home).join(".local").join("share")
+    fn open_workspace(&mut self, path: std::path::PathBuf) {
+        if self.store.path() == Some(path.as_path()) {
+            return;
+        }
+        let store = storage::open(path.clone());
+        let app = match store.load() {
+            Ok(app) => app.unwrap_or_default(),
+            Err(e) => {
+                self.workspace_error = Some(format!("{}: {}", store.describe(), e));
+                return;
+            }
         };
-        Some(data_dir.join("tree_notes").join("data.json"))
+
+        self.save_to_disk();
+        let journal_seq = app.journal_seq;
+        self.replace_data(app);
+        self.comment_drafts.clear();
+        self.store = store;
+        self.journal_seq = journal_seq;
+        self.replay_journal();
+        self.save_to_disk();
+
+        self.workspaces.touch(&path);
+        self.workspace_error = self.workspaces.save().err();
     }
 
-    fn save_to_disk(&mut self) {
-        #[cfg(not(target_arch = "wasm32"))]
-        if let Ok(json) = serde_json::to_string(self) {
-            if let Some(path) = Self::get_data_path() {
-                if let Some(parent) = path.parent() {
-                    let _ = std::fs::create_dir_all(parent);
+    /// 今のトラッカーを隣の `<name>.sqlite` に書き出して、以降はそちらに保存する。
+    /// 元のファイルはそのまま残す。
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    fn convert_to_sqlite(&mut self) {
+        let Some(path) = self.store.path().map(|p| p.to_path_buf()) else {
+            self.workspace_error = Some("this workspace has no file to convert".to_string());
+            return;
+        };
+        if sqlite_store::is_sqlite_path(&path) {
+            return;
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let stem = stem.trim_start_matches('.');
+        let target = path.with_file_name(format!("{}.sqlite", stem));
+
+        self.save_to_disk();
+        if let Err(e) = sqlite_store::export(self, &target) {
+            self.workspace_error = Some(e);
+            return;
+        }
+        self.open_workspace(target);
+    }
+
+    fn restore_snapshot(&mut self, id: &str) {
+        match self.store.load_snapshot(id) {
+            Ok(app) => {
+                self.snapshot_before_replace();
+                self.replace_data(app);
+                // 復旧画面から復元した場合はここで保存を再開する
+                self.load_error = None;
+                self.save_to_disk();
+                self.restore_error = None;
+                self.show_restore = false;
+            }
+            Err(e) => self.restore_error = Some(e),
+        }
+    }
+
+    fn show_restore_window(&mut self, ctx: &egui::Context) {
+        if !self.show_restore {
+            return;
+        }
+        let mut restore_target = None;
+        let mut refresh = false;
+        egui::Window::new("Restore from Backup")
+            .open(&mut self.show_restore)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Snapshots of {}", self.store.describe()));
+                    if ui.button("Refresh").clicked() {
+                        refresh = true;
+                    }
+                });
+                ui.separator();
+
+                if self.backups.is_empty() {
+                    ui.label("No backups yet.");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("backup_grid").striped(true).show(ui, |ui| {
+                        ui.strong("Last modified");
+                        ui.strong("Issues");
+                        ui.strong("Users");
+                        ui.end_row();
+
+                        for info in &self.backups {
+                            ui.label(info.modified.format("%Y-%m-%d %H:%M:%S").to_string());
+                            match (info.issue_count, info.user_count) {
+                                (Some(issues), Some(users)) => {
+                                    ui.label(issues.to_string());
+                                    ui.label(users.to_string());
+                                    if ui.button("Restore").clicked() {
+                                        restore_target = Some(info.id.clone());
+                                    }
+                                }
+                                _ => {
+                                    ui.label("-");
+                                    ui.label("-");
+                                    ui.label(
+                                        egui::RichText::new("unreadable").color(egui::Color32::RED),
+                                    );
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                if let Some(err) = &self.restore_error {
+                    ui.separator();
+                    ui.label(egui::RichText::new(err).color(egui::Color32::RED));
+                }
+            });
+
+        if let Some(id) = restore_target {
+            self.restore_snapshot(&id);
+        }
+        if refresh {
+            self.refresh_backups();
+        }
+    }
+
+    /// ファイルを読み込んだ結果を確認用に並べる。ここではまだ何も変更しない。
+    fn preview_import(&mut self, file: ImportedFile) {
+        let data = match file.data {
+            Ok(ImportedData::Csv(table)) => {
+                self.csv_import = Some(CsvImport {
+                    name: file.name,
+                    mapping: csv::Mapping::guess(&table.headers),
+                    table,
+                    error: None,
+                });
+                return;
+            }
+            Ok(ImportedData::Tracker(data)) => Ok((data, false)),
+            Ok(ImportedData::Converted(data)) => Ok((data, true)),
+            Err(e) => Err(e),
+        };
+        let preview = data.and_then(|(data, converted)| {
+            Ok(ImportPreview {
+                plan: merge::plan(self, &data)?,
+                diff: merge::diff(self, &data),
+                data,
+                converted,
+            })
+        });
+        self.pending_import = Some(PendingImport {
+            name: file.name,
+            preview,
+        });
+    }
+
+    fn show_import_window(&mut self, ctx: &egui::Context) {
+        let Some(pending) = &mut self.pending_import else {
+            return;
+        };
+        let mut open = true;
+        let mut merge = false;
+        let mut replace = false;
+        let mut cancel = false;
+        let (current_issues, current_users) =
+            (self.issues.get_list().len(), self.users.get_list().len());
+        egui::Window::new(format!("Import {}", pending.name))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let preview = match &mut pending.preview {
+                    Ok(preview) => preview,
+                    Err(report) => {
+                        ui.label(
+                            egui::RichText::new("This file can't be imported.")
+                                .color(egui::Color32::RED),
+                        );
+                        ui.add_space(5.0);
+                        egui::ScrollArea::both().max_height(300.0).show(ui, |ui| {
+                            ui.label(egui::RichText::new(report.as_str()).monospace());
+                        });
+                        ui.separator();
+                        if ui.button("Close").clicked() {
+                            cancel = true;
+                        }
+                        return;
+                    }
+                };
+
+                egui::Grid::new("import_counts").show(ui, |ui| {
+                    ui.label("");
+                    ui.strong("Current");
+                    ui.strong("File");
+                    ui.end_row();
+                    ui.label("Issues");
+                    ui.label(current_issues.to_string());
+                    ui.label(preview.data.issues.get_list().len().to_string());
+                    ui.end_row();
+                    ui.label("Users");
+                    ui.label(current_users.to_string());
+                    ui.label(preview.data.users.get_list().len().to_string());
+                    ui.end_row();
+                });
+
+                ui.separator();
+                egui::CollapsingHeader::new(format!("Differences ({})", preview.diff.len()))
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        if preview.diff.is_empty() {
+                            ui.label("The issues in the file are the same as the current ones.");
+                        }
+                        egui::ScrollArea::vertical()
+                            .id_salt("import_diff")
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                for entry in &preview.diff {
+                                    let (mark, color) = match entry.change {
+                                        merge::Change::Added => ("+", egui::Color32::GREEN),
+                                        merge::Change::Removed => ("-", egui::Color32::RED),
+                                        merge::Change::Changed => ("~", egui::Color32::YELLOW),
+                                    };
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            egui::RichText::new(mark).color(color).monospace(),
+                                        );
+                                        ui.label(&entry.title);
+                                        ui.label(egui::RichText::new(&entry.detail).weak());
+                                    });
+                                }
+                            });
+                    });
+
+                ui.separator();
+                let plan = &mut preview.plan;
+                ui.strong("Merge");
+                ui.label(format!(
+                    "{} new issues, {} new comments, {} new users, {} new labels, \
+                     {} deletions, {} edits",
+                    plan.new_issues,
+                    plan.new_comments,
+                    plan.new_users,
+                    plan.new_labels,
+                    plan.new_deletions,
+                    plan.new_edits
+                ));
+
+                if !plan.conflicts.is_empty() {
+                    ui.add_space(5.0);
+                    ui.label("These issues have a different status in the imported file.");
+                    egui::ScrollArea::vertical()
+                        .id_salt("import_conflicts_scroll")
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("import_conflicts")
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    ui.strong("Issue");
+                                    ui.strong("Current");
+                                    ui.strong("Imported");
+                                    ui.strong("Use imported");
+                                    ui.end_row();
+                                    for conflict in &mut plan.conflicts {
+                                        ui.label(format!(
+                                            "#{} {}",
+                                            self.ids.number(conflict.issue),
+                                            conflict.title
+                                        ));
+                                        ui.label(conflict.ours);
+                                        ui.label(conflict.theirs);
+                                        ui.add_enabled(
+                                            conflict.take_theirs.is_some(),
+                                            egui::Checkbox::without_text(&mut conflict.use_theirs),
+                                        )
+                                        .on_disabled_hover_text("A forked status can't be applied");
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                }
+
+                ui.separator();
+                ui.label("Nothing is changed until you choose below.");
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!plan.is_empty(), egui::Button::new("Merge"))
+                        .clicked()
+                    {
+                        merge = true;
+                    }
+                    if ui
+                        .button("Replace Everything")
+                        .on_hover_text("Discard the current data and use the imported file as is")
+                        .clicked()
+                    {
+                        replace = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+
+        let (name, preview) = match self.pending_import.take() {
+            Some(PendingImport {
+                name,
+                preview: Ok(preview),
+            }) if merge || replace => (name, preview),
+            pending => {
+                if !cancel && open {
+                    self.pending_import = pending;
+                }
+                return;
+            }
+        };
+        if merge {
+            // 確認している間に Issue が増えていると計画の index がずれるので、今のデータで計画し直す
+            let mut plan = match merge::plan(self, &preview.data) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    self.pending_import = Some(PendingImport {
+                        name,
+                        preview: Err(e),
+                    });
+                    return;
                 }
-                let _ = std::fs::write(path, json);
+            };
+            plan.keep_choices(&preview.plan);
+            self.snapshot_before_replace();
+            let label = format!(
+                "import {} issues and {} comments",
+                plan.new_issues, plan.new_comments
+            );
+            self.perform_all(label, plan.into_ops());
+        } else {
+            self.snapshot_before_replace();
+            let mut data = *preview.data;
+            // 変換したものには個人の状態が無いので、今のユーザーとフィルタのままにする
+            if preview.converted {
+                data.current_user = self.current_user.clone();
+                data.filter_status = self.filter_status;
             }
+            self.replace_data(data);
         }
     }
 
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        Self::setup_custom_fonts(&cc.egui_ctx);
+    /// 取り込むファイルを選んでもらう。読み込んだ結果は `import_rx` に届く。
+    fn pick_import_file(&self, filter: Option<(&'static str, &'static [&'static str])>) {
+        let Some(tx) = self.import_tx.clone() else {
+            return;
+        };
+        let author = self.current_user.clone();
+
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut dialog = rfd::AsyncFileDialog::new();
+            if let Some((name, extensions)) = filter {
+                dialog = dialog.add_filter(name, extensions);
+            }
+            if let Some(file) = dialog.pick_file().await {
+                let data = file.read().await;
+                let _ = tx.send(ImportedFile::parse(file.file_name(), &data, &author));
+            }
+        });
 
         #[cfg(not(target_arch = "wasm32"))]
-        if let Some(path) = Self::get_data_path() {
-            if path.exists() {
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Ok(mut app) = serde_json::from_str::<Self>(&content) {
-                        let (tx, rx) = channel();
-                        app.import_rx = Some(rx);
-                        app.import_tx = Some(tx);
-                        return app;
+        std::thread::spawn(move || {
+            let mut dialog = rfd::FileDialog::new();
+            if let Some((name, extensions)) = filter {
+                dialog = dialog.add_filter(name, extensions);
+            }
+            if let Some(path) = dialog.pick_file() {
+                let _ = tx.send(ImportedFile::read(&path, &author));
+            }
+        });
+    }
+
+    /// GitHub の issues と comments の JSON を一緒に選んでもらい、まとめて変換する
+    fn pick_github_files(&self) {
+        let Some(tx) = self.import_tx.clone() else {
+            return;
+        };
+        let convert = |files: Vec<(String, String)>| ImportedFile {
+            name: "GitHub issues".to_string(),
+            data: github::convert(&files)
+                .map(|(issues, users)| ImportedData::converted(issues, users)),
+        };
+
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            let Some(handles) = rfd::AsyncFileDialog::new()
+                .add_filter("JSON", &["json"])
+                .pick_files()
+                .await
+            else {
+                return;
+            };
+            let mut files = Vec::new();
+            for handle in handles {
+                let text = String::from_utf8_lossy(&handle.read().await).into_owned();
+                files.push((handle.file_name(), text));
+            }
+            let _ = tx.send(convert(files));
+        });
+
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(move || {
+            let Some(paths) = rfd::FileDialog::new()
+                .add_filter("JSON", &["json"])
+                .pick_files()
+            else {
+                return;
+            };
+            let mut files = Vec::new();
+            for path in paths {
+                let name = path.display().to_string();
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => files.push((name, text)),
+                    Err(e) => {
+                        let _ = tx.send(ImportedFile {
+                            name,
+                            data: Err(format!("could not read file: {}", e)),
+                        });
+                        return;
                     }
                 }
             }
+            let _ = tx.send(convert(files));
+        });
+    }
+
+    /// CSV の列の対応付けを選んでもらい、Issue にしてから通常の取り込みの確認に進む
+    fn show_csv_import_window(&mut self, ctx: &egui::Context) {
+        let Some(import) = &mut self.csv_import else {
+            return;
+        };
+        let mut open = true;
+        let mut next = false;
+        let mut cancel = false;
+        egui::Window::new(format!("Import {}", import.name))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} rows. Choose which column to read for each field.",
+                    import.table.rows.len()
+                ));
+                ui.add_space(5.0);
+                egui::Grid::new("csv_mapping")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Field");
+                        ui.strong("Column");
+                        ui.strong("First row");
+                        ui.end_row();
+                        for field in csv::Field::ALL {
+                            ui.label(field.label());
+                            let column = import.mapping.get_mut(field);
+                            let selected = match *column {
+                                Some(c) => import.table.headers[c].clone(),
+                                None => "(none)".to_string(),
+                            };
+                            egui::ComboBox::from_id_salt(field.label())
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(column, None, "(none)");
+                                    for (i, header) in import.table.headers.iter().enumerate() {
+                                        ui.selectable_value(column, Some(i), header);
+                                    }
+                                });
+                            let sample = column.map(|c| import.table.cell(0, c)).unwrap_or("");
+                            ui.label(egui::RichText::new(sample).weak());
+                            ui.end_row();
+                        }
+                    });
+
+                if let Some(error) = &import.error {
+                    ui.add_space(5.0);
+                    ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            import.mapping.get(csv::Field::Title).is_some(),
+                            egui::Button::new("Next"),
+                        )
+                        .on_disabled_hover_text("Choose the column to use as the title")
+                        .clicked()
+                    {
+                        next = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+
+        if cancel || !open {
+            self.csv_import = None;
+            return;
+        }
+        if !next {
+            return;
+        }
+        let Some(import) = self.csv_import.take() else {
+            return;
+        };
+        match csv::build(&import.table, &import.mapping, &self.current_user) {
+            Ok((issues, users)) => {
+                self.preview_import(ImportedFile {
+                    name: import.name,
+                    data: Ok(ImportedData::converted(issues, users)),
+                });
+            }
+            Err(e) => {
+                self.csv_import = Some(CsvImport {
+                    error: Some(e),
+                    ..import
+                });
+            }
         }
+    }
 
-        if let Some(storage) = cc.storage
-            && let Some(json) = storage.get_string(eframe::APP_KEY)
-            && let Ok(mut app) = serde_json::from_str::<Self>(&json)
+    /// ウィンドウにドロップされたファイルを取り込む。複数ある場合は最初の1つだけ。
+    /// wasm 版では中身が `bytes` に、ネイティブ版ではパスが `path` に入ってくる。
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let is_hovering = ctx.input(|i| !i.raw.hovered_files.is_empty());
+        if is_hovering {
+            egui::Area::new(egui::Id::new("drop_overlay"))
+                .order(egui::Order::Foreground)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style())
+                        .inner_margin(20.0)
+                        .show(ui, |ui| {
+                            ui.heading("Drop to import");
+                        });
+                });
+        }
+
+        let Some(dropped) = ctx.input(|i| i.raw.dropped_files.first().cloned()) else {
+            return;
+        };
+        if let Some(bytes) = &dropped.bytes {
+            let file = ImportedFile::parse(dropped.name.clone(), bytes, &self.current_user);
+            self.preview_import(file);
+            return;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = dropped.path
+            && let Some(tx) = self.import_tx.clone()
+        {
+            let author = self.current_user.clone();
+            std::thread::spawn(move || {
+                let _ = tx.send(ImportedFile::read(&path, &author));
+            });
+        }
+    }
+
+    fn with_import_channel(mut self) -> Self {
+        let (tx, rx) = channel();
+        self.import_rx = Some(rx);
+        self.import_tx = Some(tx);
+        #[cfg(not(target_arch = "wasm32"))]
         {
             let (tx, rx) = channel();
-            app.import_rx = Some(rx);
-            app.import_tx = Some(tx);
-            return app;
+            self.open_path_rx = Some(rx);
+            self.open_path_tx = Some(tx);
+        }
+        self
+    }
+
+    /// 保存先から読み込む。まだ何も保存されていなければ空のトラッカーになる。
+    fn open_store(store: Box<dyn storage::Store>) -> Self {
+        match store.load() {
+            Ok(loaded) => {
+                let mut app = loaded.unwrap_or_default().with_import_channel();
+                app.store = store;
+                app.replay_journal();
+                app
+            }
+            Err(e) => Self::load_failed(store, e),
+        }
+    }
+
+    /// 読み込みに失敗したときの状態。壊れたデータを退避してから復旧画面を出し、
+    /// ユーザーが復旧方法を選ぶまで保存は一切しない。
+    fn load_failed(mut store: Box<dyn storage::Store>, error: schema::LoadError) -> Self {
+        let mut message = format!("{}: {}", store.describe(), error);
+        let mut quarantined_to = None;
+        if error.is_corrupt() {
+            match store.quarantine() {
+                Ok(to) => quarantined_to = Some(to),
+                Err(e) => message.push_str(&format!("\n({})", e)),
+            }
+        }
+        // 退避できなかった場合は "Start Fresh" で元のデータを上書きしてしまうので出さない
+        let can_start_fresh = quarantined_to.is_some();
+        let mut app = Self {
+            load_error: Some(LoadFailure {
+                message,
+                quarantined_to,
+                can_start_fresh,
+            }),
+            store,
+            ..Self::default()
+        };
+        app.refresh_backups();
+        app
+    }
+
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        Self::setup_custom_fonts(&cc.egui_ctx);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut workspaces = workspace::WorkspaceConfig::load();
+            let mut workspace_error = None;
+            let data_path = match startup_path_from_args() {
+                Some(path) => {
+                    workspaces.touch(&path);
+                    workspace_error = workspaces.save().err();
+                    Some(path)
+                }
+                None => workspaces.startup_path(),
+            };
+            // データディレクトリが決められない場合はメモリ上だけで動かす
+            let store = data_path
+                .map(storage::open)
+                .unwrap_or_else(|| Box::new(storage::MemoryStore::new()));
+            let mut app = Self::open_store(store);
+            app.workspaces = workspaces;
+            app.workspace_error = workspace_error;
+            app
         }
 
-        Self::default()
+        #[cfg(target_arch = "wasm32")]
+        Self::open_store(Box::new(storage::EframeStore::new()))
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -221,30 +1451,92 @@ impl TreeNotesApp {
 // ... existing App impl ...
 
 impl eframe::App for TreeNotesApp {
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        if let Ok(json) = serde_json::to_string(self) {
-            storage.set_string(eframe::APP_KEY, json);
+    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+        if self.dirty {
             self.save_to_disk();
         }
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // 表示の絞り込みも保存するので、変わったら保存する
+        let filter_before = self.filter_status;
+        if let Some(failure) = &self.load_error {
+            let mut start_fresh = false;
+            let mut open_restore = false;
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.heading("Could not load your data");
+                ui.label(
+                    "Tree Notes did not start with an empty tracker so that your existing \
+                     data is not overwritten. Nothing will be saved until you choose how to recover.",
+                );
+                ui.add_space(10.0);
+                ui.label(
+                    egui::RichText::new(&failure.message)
+                        .color(egui::Color32::RED)
+                        .monospace(),
+                );
+                if let Some(quarantined_to) = &failure.quarantined_to {
+                    ui.add_space(5.0);
+                    ui.label(format!("The unreadable data was moved to: {}", quarantined_to));
+                }
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Restore from Backup").clicked() {
+                        open_restore = true;
+                    }
+                    if failure.can_start_fresh && ui.button("Start Fresh").clicked() {
+                        start_fresh = true;
+                    }
+                    if ui.button("Exit").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+            });
+
+            if open_restore {
+                self.refresh_backups();
+                self.show_restore = true;
+            }
+            self.show_restore_window(ctx);
+
+            if start_fresh {
+                self.load_error = None;
+                self.journal_seq = self.journal_end();
+            }
+            return;
+        }
+
         // --- 0. Handle Async Data Import ---
         if let Some(rx) = &self.import_rx
-            && let Ok(new_app) = rx.try_recv()
+            && let Ok(file) = rx.try_recv()
         {
-            self.issues = new_app.issues;
-            self.users = new_app.users;
-            self.current_user = new_app.current_user;
-            self.filter_status = new_app.filter_status;
-            self.selected_issue_index = None;
+            self.preview_import(file);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = self.open_path_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.open_workspace(path);
         }
 
+        self.handle_dropped_files(ctx);
+
         // --- 1. Keybindings & Action Flags ---
         let mut trigger_import = false;
+        let mut trigger_import_csv = false;
+        let mut trigger_import_github = false;
+        let mut trigger_import_todo_txt = false;
+        let mut trigger_import_taskwarrior = false;
         let mut trigger_export = false;
         let mut trigger_save = false;
         let mut trigger_exit = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut trigger_open_workspace: Option<std::path::PathBuf> = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut trigger_pick_tracker = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut trigger_new_tracker = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut trigger_pick_repository = false;
 
         let mut trigger_new_issue_focus = false;
         let mut trigger_comment_focus = false; // "Add a comment"
@@ -252,14 +1544,25 @@ impl eframe::App for TreeNotesApp {
         let mut trigger_comment_submit = false; // "Comment" action
 
         let mut trigger_fork = false;
+        let mut trigger_delete_issue = false;
         let mut trigger_close_cmp = false;
         let mut trigger_close_not_planned = false;
+        let mut trigger_undo = false;
+        let mut trigger_redo = false;
+
+        // パネルの中で決まった操作。描画が終わってから `perform` する。
+        let mut pending_op: Option<journal::Op> = None;
+        // 複数の操作をまとめて1回で取り消せるようにするもの
+        let mut pending_batch: Option<(String, Vec<journal::Op>)> = None;
 
         // IDs for focus
         let new_issue_id = egui::Id::new("new_issue_input");
         let search_id = egui::Id::new("search_input");
         let comment_id = egui::Id::new("comment_input");
 
+        // テキスト入力中の Ctrl+Z は TextEdit 自身の取り消しに任せる
+        let editing_text = ctx.wants_keyboard_input();
+
         // Check Keybinds
         ctx.input(|i| {
             if i.modifiers.command {
@@ -287,6 +1590,13 @@ impl eframe::App for TreeNotesApp {
                 if i.key_pressed(egui::Key::A) {
                     trigger_comment_focus = true;
                 }
+                if i.key_pressed(egui::Key::Z) && !editing_text {
+                    if i.modifiers.shift {
+                        trigger_redo = true;
+                    } else {
+                        trigger_undo = true;
+                    }
+                }
                 // Ctrl+Enter for comment submit (only if comment box is focused check later or global?)
                 // The request says "when focused comment box".
                 // We can check if comment box is focused using memory.
@@ -312,20 +1622,203 @@ impl eframe::App for TreeNotesApp {
                         trigger_export = true;
                         ui.close_menu();
                     }
+                    if ui.button("Export as Markdown").clicked() {
+                        self.markdown_export = Some(markdown::Options::default());
+                        ui.close();
+                    }
+                    if ui.button("Export as CSV").clicked() {
+                        export::save_file(
+                            "tree_notes.csv",
+                            csv::render(&self.issues, &self.ids, &self.trash),
+                        );
+                        ui.close();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Export as HTML Site").clicked() {
+                        export::save_to_folder(html::render_site(
+                            &self.issues,
+                            &self.ids,
+                            &self.trash,
+                            &self.labels,
+                        ));
+                        ui.close();
+                    }
+                    if ui.button("Export to todo.txt").clicked() {
+                        export::save_file(
+                            "todo.txt",
+                            todotxt::render(&self.issues, &self.ids, &self.trash),
+                        );
+                        ui.close();
+                    }
+                    if ui.button("Export to Taskwarrior").clicked() {
+                        export::save_file(
+                            "tasks.json",
+                            taskwarrior::render(&self.issues, &self.ids, &self.trash),
+                        );
+                        ui.close();
+                    }
+                    ui.menu_button("Export to GitHub", |ui| {
+                        if ui
+                            .button("gh Script")
+                            .on_hover_text("A shell script of `gh issue create` commands")
+                            .clicked()
+                        {
+                            export::save_file(
+                                "create_issues.sh",
+                                github::render_script(&self.issues, &self.ids, &self.trash),
+                            );
+                            ui.close();
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui
+                            .button("JSON Payloads")
+                            .on_hover_text("One directory of REST API payloads per issue")
+                            .clicked()
+                        {
+                            export::save_to_folder(github::render_payloads(
+                                &self.issues,
+                                &self.ids,
+                                &self.trash,
+                            ));
+                            ui.close();
+                        }
+                    });
                     if ui.button("Import from Json").clicked() {
                         trigger_import = true;
                         ui.close_menu();
                     }
+                    if ui.button("Import from CSV").clicked() {
+                        trigger_import_csv = true;
+                        ui.close();
+                    }
+                    if ui
+                        .button("Import from GitHub")
+                        .on_hover_text("Select the issues JSON and, optionally, the comments JSON")
+                        .clicked()
+                    {
+                        trigger_import_github = true;
+                        ui.close();
+                    }
+                    if ui.button("Import from todo.txt").clicked() {
+                        trigger_import_todo_txt = true;
+                        ui.close();
+                    }
+                    if ui
+                        .button("Import from Taskwarrior")
+                        .on_hover_text("The output of `task export`")
+                        .clicked()
+                    {
+                        trigger_import_taskwarrior = true;
+                        ui.close();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    ui.menu_button("Workspace", |ui| {
+                        let mut workspace_paths: Vec<std::path::PathBuf> =
+                            workspace::default_path().into_iter().collect();
+                        workspace_paths.extend(
+                            workspace::list_named()
+                                .iter()
+                                .filter_map(|name| workspace::named_path(name)),
+                        );
+                        for path in workspace_paths {
+                            let is_current = self.store.path() == Some(path.as_path());
+                            if ui
+                                .selectable_label(is_current, workspace::display_name(&path))
+                                .clicked()
+                            {
+                                trigger_open_workspace = Some(path);
+                                ui.close();
+                            }
+                        }
+                        ui.separator();
+                        ui.menu_button("Recent", |ui| {
+                            if self.workspaces.recent.is_empty() {
+                                ui.label("No recent workspaces");
+                            }
+                            for path in &self.workspaces.recent {
+                                if ui.button(workspace::display_name(path)).clicked() {
+                                    trigger_open_workspace = Some(path.clone());
+                                    ui.close();
+                                }
+                            }
+                        });
+                        if ui.button("New Workspace").clicked() {
+                            self.show_new_workspace = true;
+                            ui.close();
+                        }
+                        if ui.button("Open Tracker File").clicked() {
+                            trigger_pick_tracker = true;
+                            ui.close();
+                        }
+                        if ui.button("New Tracker File").clicked() {
+                            trigger_new_tracker = true;
+                            ui.close();
+                        }
+                        if ui.button("Open Repository Tracker").clicked() {
+                            trigger_pick_repository = true;
+                            ui.close();
+                        }
+                        #[cfg(feature = "sqlite")]
+                        if ui.button("Convert to SQLite").clicked() {
+                            self.convert_to_sqlite();
+                            ui.close();
+                        }
+                    });
+                    if ui.button("Restore from Backup").clicked() {
+                        self.refresh_backups();
+                        self.show_restore = true;
+                        ui.close();
+                    }
+                    if ui.button("History").clicked() {
+                        self.refresh_history();
+                        self.show_history = true;
+                        ui.close();
+                    }
                     if ui.button("Manage Users").clicked() {
                         self.show_user_manager = true;
                         ui.close_menu();
                     }
+                    if ui.button("Manage Labels").clicked() {
+                        self.show_label_manager = true;
+                        ui.close();
+                    }
                     if ui.button("Exit").clicked() {
                         trigger_exit = true;
                         ui.close_menu();
                     }
                 });
 
+                ui.menu_button("Edit", |ui| {
+                    let undo_label = self.undo.undo_label();
+                    if ui
+                        .add_enabled(
+                            undo_label.is_some(),
+                            egui::Button::new(match &undo_label {
+                                Some(label) => format!("Undo: {}", label),
+                                None => "Undo".to_string(),
+                            }),
+                        )
+                        .clicked()
+                    {
+                        trigger_undo = true;
+                        ui.close();
+                    }
+                    let redo_label = self.undo.redo_label();
+                    if ui
+                        .add_enabled(
+                            redo_label.is_some(),
+                            egui::Button::new(match &redo_label {
+                                Some(label) => format!("Redo: {}", label),
+                                None => "Redo".to_string(),
+                            }),
+                        )
+                        .clicked()
+                    {
+                        trigger_redo = true;
+                        ui.close();
+                    }
+                });
+
                 ui.menu_button("Issue", |ui| {
                     if ui.button("New Issue").clicked() {
                         trigger_new_issue_focus = true;
@@ -345,6 +1838,10 @@ impl eframe::App for TreeNotesApp {
                         trigger_fork = true;
                         ui.close_menu();
                     }
+                    if ui.button("Delete Issue").clicked() {
+                        trigger_delete_issue = true;
+                        ui.close();
+                    }
                     if ui.button("Go to Comment").clicked() {
                         trigger_comment_focus = true;
                         ui.close_menu();
@@ -398,6 +1895,10 @@ impl eframe::App for TreeNotesApp {
                         trigger_search_focus = true;
                         ui.close_menu();
                     }
+                    if ui.button("Trash").clicked() {
+                        self.show_trash = true;
+                        ui.close();
+                    }
                 });
 
                 ui.menu_button("Help", |ui| {
@@ -406,65 +1907,81 @@ impl eframe::App for TreeNotesApp {
                         ui.close_menu();
                     }
                 });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    let workspace_name = match self.store.path() {
+                        Some(path) => workspace::display_name(path),
+                        None => self.store.describe(),
+                    };
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(
+                            egui::RichText::new(format!("Workspace: {}", workspace_name)).weak(),
+                        );
+                    });
+                }
             });
         });
 
         // --- 3. Execute Actions ---
 
         if trigger_exit {
+            // 閉じる前に待たずに保存する
+            if self.dirty {
+                self.save_to_disk();
+            }
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
         }
 
-        let current_time = ctx.input(|i| i.time);
-        if trigger_save || (current_time - self.last_auto_save > 30.0) {
+        if trigger_save {
             self.save_to_disk();
-            self.last_auto_save = current_time;
-            self.last_save_time = current_time;
+            self.last_save_time = ctx.input(|i| i.time);
         }
 
-        if trigger_import && let Some(tx) = self.import_tx.clone() {
-            #[cfg(target_arch = "wasm32")]
-            wasm_bindgen_futures::spawn_local(async move {
-                if let Some(file) = rfd::AsyncFileDialog::new().pick_file().await {
-                    let data = file.read().await;
-                    if let Ok(app) = serde_json::from_slice::<TreeNotesApp>(&data) {
-                        let _ = tx.send(app);
-                    }
-                }
-            });
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = trigger_open_workspace {
+            self.open_workspace(path);
+        }
 
-            #[cfg(not(target_arch = "wasm32"))]
+        #[cfg(not(target_arch = "wasm32"))]
+        if (trigger_pick_tracker || trigger_new_tracker || trigger_pick_repository)
+            && let Some(tx) = self.open_path_tx.clone()
+        {
             std::thread::spawn(move || {
-                if let Some(path) = rfd::FileDialog::new().pick_file()
-                    && let Ok(content) = std::fs::read_to_string(path)
-                    && let Ok(app) = serde_json::from_str::<TreeNotesApp>(&content)
-                {
-                    let _ = tx.send(app);
+                let dialog = rfd::FileDialog::new().add_filter("Tree Notes", &["json"]);
+                let picked = if trigger_pick_repository {
+                    rfd::FileDialog::new()
+                        .pick_folder()
+                        .map(|dir| dir_store::resolve(&dir))
+                } else if trigger_new_tracker {
+                    dialog.set_file_name("data.json").save_file()
+                } else {
+                    dialog.pick_file()
+                };
+                if let Some(path) = picked {
+                    let _ = tx.send(path);
                 }
             });
         }
 
-        if trigger_export && let Ok(json) = serde_json::to_string_pretty(self) {
-            #[cfg(target_arch = "wasm32")]
-            wasm_bindgen_futures::spawn_local(async move {
-                if let Some(handle) = rfd::AsyncFileDialog::new()
-                    .set_file_name("tree_notes_export.json")
-                    .save_file()
-                    .await
-                {
-                    let _ = handle.write(json.as_bytes()).await;
-                }
-            });
+        if trigger_import {
+            self.pick_import_file(None);
+        }
+        if trigger_import_csv {
+            self.pick_import_file(Some(("CSV", &["csv"])));
+        }
+        if trigger_import_github {
+            self.pick_github_files();
+        }
+        if trigger_import_todo_txt {
+            self.pick_import_file(Some(("todo.txt", &["txt"])));
+        }
+        if trigger_import_taskwarrior {
+            self.pick_import_file(Some(("Taskwarrior export", &["json"])));
+        }
 
-            #[cfg(not(target_arch = "wasm32"))]
-            std::thread::spawn(move || {
-                if let Some(path) = rfd::FileDialog::new()
-                    .set_file_name("tree_notes_export.json")
-                    .save_file()
-                {
-                    let _ = std::fs::write(path, json);
-                }
-            });
+        if trigger_export && let Ok(json) = serde_json::to_string_pretty(self) {
+            export::save_file("tree_notes_export.json", json);
         }
 
         if trigger_new_issue_focus {
@@ -479,34 +1996,48 @@ impl eframe::App for TreeNotesApp {
             ctx.memory_mut(|m| m.request_focus(comment_id));
         }
 
-        if let Some(id) = self.selected_issue_index {
-            if trigger_fork {
-                 if let Some(new_id) = self.issues.fork(id) {
-                    self.filter_status = FilterStatus::All;
-                    self.selected_issue_index = Some(new_id);
-                }
+        if trigger_undo {
+            self.undo();
+        }
+        if trigger_redo {
+            self.redo();
+        }
+
+        if let Some(id) = self.selected_index() {
+            if trigger_fork && self.issues.get(id).is_some() {
+                self.perform(journal::Op::Fork {
+                    issue: self.ids.key(id),
+                    uuid: Some(ids::new_uuid()),
+                });
             }
-            if trigger_close_cmp {
-                if let Some(target) = self.issues.get_mut(id) {
-                    target.close_as_cmp();
-                }
+            if trigger_close_cmp && self.issues.get(id).is_some() {
+                self.perform(journal::Op::CloseAsCmp {
+                    issue: self.ids.key(id),
+                });
             }
-            if trigger_close_not_planned {
-                if let Some(target) = self.issues.get_mut(id) {
-                    target.close_as_not_planed();
-                }
+            if trigger_close_not_planned && self.issues.get(id).is_some() {
+                self.perform(journal::Op::CloseAsNotPlaned {
+                    issue: self.ids.key(id),
+                });
+            }
+            if trigger_delete_issue && !self.trash.is_deleted_at(&self.ids, id, None) {
+                self.perform(journal::Op::Delete {
+                    issue: self.ids.key(id),
+                    comment: None,
+                    by: self.current_user.clone(),
+                    date: chrono::Local::now(),
+                });
             }
 
             if trigger_comment_submit {
-                let draft_text = self.comment_drafts.entry(id).or_default();
-                if !draft_text.is_empty() {
-                    if let Some(target_issue) = self.issues.get_mut(id) {
-                        target_issue.comment(Comment::new(
-                            draft_text.clone(),
-                            self.current_user.clone(),
-                        ));
-                        draft_text.clear();
-                    }
+                let draft_text = self.comment_drafts.entry(self.ids.number(id)).or_default();
+                if !draft_text.is_empty() && self.issues.get(id).is_some() {
+                    let comment =
+                        Comment::new(std::mem::take(draft_text), self.current_user.clone());
+                    self.perform(journal::Op::Comment {
+                        issue: self.ids.key(id),
+                        comment,
+                    });
                 }
             }
         }
@@ -523,6 +2054,84 @@ impl eframe::App for TreeNotesApp {
                 });
         }
 
+        self.show_restore_window(ctx);
+        self.show_import_window(ctx);
+        self.show_csv_import_window(ctx);
+        self.show_markdown_export_window(ctx);
+
+        if self.show_history {
+            egui::Window::new("History")
+                .open(&mut self.show_history)
+                .show(ctx, |ui| {
+                    if self.history.is_empty() {
+                        ui.label("No history yet.");
+                    }
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        egui::Grid::new("history_grid")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                let undone = journal::undone(&self.history);
+                                for entry in &self.history {
+                                    ui.label(entry.at.format("%Y-%m-%d %H:%M:%S").to_string());
+                                    ui.label(&entry.by);
+                                    let description =
+                                        egui::RichText::new(entry.op.describe(&self.ids));
+                                    // 取り消した操作は消さずに線を引く
+                                    ui.label(if undone.contains(&entry.seq) {
+                                        description.strikethrough().weak()
+                                    } else {
+                                        description
+                                    });
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                });
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.show_new_workspace {
+            let mut create = None;
+            egui::Window::new("New Workspace")
+                .open(&mut self.show_new_workspace)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Name:");
+                        ui.text_edit_singleline(&mut self.new_workspace_name);
+                    });
+                    if ui.button("Create").clicked() {
+                        create = Some(self.new_workspace_name.trim().to_string());
+                    }
+                });
+
+            if let Some(name) = create {
+                match workspace::validate_name(&name).and_then(|_| {
+                    workspace::named_path(&name)
+                        .ok_or_else(|| "could not determine data directory".to_string())
+                }) {
+                    Ok(path) => {
+                        self.open_workspace(path);
+                        self.new_workspace_name.clear();
+                        self.show_new_workspace = false;
+                    }
+                    Err(e) => self.workspace_error = Some(e),
+                }
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(err) = self.workspace_error.clone() {
+            let mut open = true;
+            egui::Window::new("Workspace Error")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.label(egui::RichText::new(err).color(egui::Color32::RED));
+                });
+            if !open {
+                self.workspace_error = None;
+            }
+        }
+
         if self.show_user_manager {
             egui::Window::new("User Manager")
                 .open(&mut self.show_user_manager)
@@ -534,6 +2143,7 @@ impl eframe::App for TreeNotesApp {
                             ui.label(format!("User: {}", user.name()));
                             if ui.button("Switch").clicked() {
                                 self.current_user = user.clone();
+                                self.dirty = true;
                             }
                             if user.name() == self.current_user.name() {
                                 ui.label("(Current)");
@@ -555,13 +2165,193 @@ impl eframe::App for TreeNotesApp {
                         && !self.new_user_email.is_empty()
                     {
                         let new_u = User::new(&self.new_user_name, &self.new_user_email);
-                        self.users.add_user(new_u);
+                        pending_op = Some(journal::Op::AddUser { user: new_u });
                         self.new_user_name.clear();
                         self.new_user_email.clear();
                     }
                 });
         }
 
+        if self.show_label_manager {
+            let mut close_form = false;
+            egui::Window::new("Labels")
+                .open(&mut self.show_label_manager)
+                .show(ctx, |ui| {
+                    if self.labels.get_list().is_empty() {
+                        ui.label(egui::RichText::new("No labels yet.").weak());
+                    }
+                    egui::Grid::new("label_list").striped(true).show(ui, |ui| {
+                        for label in self.labels.get_list() {
+                            self.labels.chip(ui, &label.name);
+                            ui.label(egui::RichText::new(&label.description).weak());
+                            let count = self
+                                .issues
+                                .get_list()
+                                .iter()
+                                .filter(|issue| issue.get_labels().contains(&label.name))
+                                .count();
+                            ui.label(format!("{} issues", count));
+                            if ui.button("Edit").clicked() {
+                                self.label_form = Some(label.clone());
+                                self.editing_label = true;
+                            }
+                            if ui
+                                .button("Delete")
+                                .on_hover_text("Also removes the label from every issue")
+                                .clicked()
+                            {
+                                pending_op = Some(journal::Op::RemoveLabel {
+                                    name: label.name.clone(),
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.separator();
+                    let Some(form) = &mut self.label_form else {
+                        if ui.button("New Label").clicked() {
+                            self.label_form = Some(self.labels.new_label(""));
+                            self.editing_label = false;
+                        }
+                        return;
+                    };
+                    ui.heading(if self.editing_label {
+                        "Edit Label"
+                    } else {
+                        "New Label"
+                    });
+                    egui::Grid::new("label_form").show(ui, |ui| {
+                        ui.label("Name:");
+                        ui.add_enabled(
+                            !self.editing_label,
+                            egui::TextEdit::singleline(&mut form.name),
+                        );
+                        ui.end_row();
+                        ui.label("Color:");
+                        ui.color_edit_button_srgb(&mut form.color);
+                        ui.end_row();
+                        ui.label("Description:");
+                        ui.text_edit_singleline(&mut form.description);
+                        ui.end_row();
+                    });
+                    let name = form.name.trim();
+                    let exists = self.labels.get(name).is_some();
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(
+                                !name.is_empty() && (self.editing_label || !exists),
+                                egui::Button::new("Save"),
+                            )
+                            .on_disabled_hover_text("A label with this name already exists")
+                            .clicked()
+                        {
+                            pending_op = Some(journal::Op::DefineLabel {
+                                label: labels::LabelDef {
+                                    name: name.to_string(),
+                                    ..form.clone()
+                                },
+                            });
+                            close_form = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            close_form = true;
+                        }
+                    });
+                });
+            if close_form {
+                self.label_form = None;
+            }
+        }
+
+        if self.show_trash {
+            let mut open_issue = None;
+            egui::Window::new("Trash")
+                .open(&mut self.show_trash)
+                .show(ctx, |ui| {
+                    if self.trash.get_list().is_empty() {
+                        ui.label(egui::RichText::new("The Trash is empty.").weak());
+                    }
+                    egui::ScrollArea::vertical()
+                        .id_salt("trash_scroll")
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("trash_list").striped(true).show(ui, |ui| {
+                                for deleted in self.trash.get_list().iter().rev() {
+                                    let Some(index) = self.ids.index_of_uuid(&deleted.issue) else {
+                                        continue;
+                                    };
+                                    let Some(issue) = self.issues.get(index) else {
+                                        continue;
+                                    };
+                                    let title =
+                                        format!("#{} {}", self.ids.number(index), issue.name());
+                                    match deleted.comment {
+                                        None => {
+                                            if ui.link(title).clicked() {
+                                                open_issue = Some(index);
+                                            }
+                                        }
+                                        Some(index) => {
+                                            let text = issue
+                                                .comments()
+                                                .get(index)
+                                                .map(|c| {
+                                                    c.text()
+                                                        .lines()
+                                                        .next()
+                                                        .unwrap_or("")
+                                                        .to_string()
+                                                })
+                                                .unwrap_or_default();
+                                            ui.vertical(|ui| {
+                                                if ui
+                                                    .link(format!("Comment on {}", title))
+                                                    .clicked()
+                                                {
+                                                    open_issue = Some(index);
+                                                }
+                                                ui.label(egui::RichText::new(text).weak());
+                                            });
+                                        }
+                                    }
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "deleted by {} on {}",
+                                            deleted.by.name(),
+                                            deleted.date.format("%Y-%m-%d %H:%M")
+                                        ))
+                                        .weak(),
+                                    );
+                                    if ui.button("Restore").clicked() {
+                                        pending_op = Some(journal::Op::Restore {
+                                            issue: ids::IssueRef::Uuid(deleted.issue.clone()),
+                                            comment: deleted.comment,
+                                        });
+                                    }
+                                    if ui
+                                        .button("Delete Permanently")
+                                        .on_hover_text(
+                                            "Removes the text. Forks keep pointing at the number.",
+                                        )
+                                        .clicked()
+                                    {
+                                        pending_op = Some(journal::Op::Purge {
+                                            issue: ids::IssueRef::Uuid(deleted.issue.clone()),
+                                            comment: deleted.comment,
+                                        });
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                });
+            if let Some(id) = open_issue {
+                self.filter_status = FilterStatus::All;
+                self.selected_issue = Some(self.ids.number(id));
+            }
+        }
+
         // --- 5. Main Panels ---
 
         // Note: Top panel is already handled by Menu Bar.
@@ -597,9 +2387,12 @@ impl eframe::App for TreeNotesApp {
                             self.new_description.clone(),
                             self.current_user.clone(),
                         ));
-                        let new_index = self.issues.add_new_issue(issue);
+                        pending_op = Some(journal::Op::AddNewIssue {
+                            issue,
+                            uuid: Some(ids::new_uuid()),
+                            parent: None,
+                        });
                         self.new_description.clear();
-                        self.selected_issue_index = Some(new_index);
                         response.request_focus();
                     }
                 });
@@ -622,6 +2415,41 @@ impl eframe::App for TreeNotesApp {
                     ui.selectable_value(&mut self.filter_status, FilterStatus::All, "All");
                 });
 
+                let label_names = self.label_names();
+                if !label_names.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label("Label:");
+                        egui::ComboBox::from_id_salt("label_filter")
+                            .selected_text(self.label_filter.as_deref().unwrap_or("Any"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.label_filter, None, "Any");
+                                for name in label_names {
+                                    let text = name.clone();
+                                    ui.selectable_value(&mut self.label_filter, Some(name), text);
+                                }
+                            });
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.people_filter, FilterPeople::Anyone, "Anyone");
+                    ui.selectable_value(
+                        &mut self.people_filter,
+                        FilterPeople::AssignedToMe,
+                        "Assigned to me",
+                    );
+                    ui.selectable_value(
+                        &mut self.people_filter,
+                        FilterPeople::CreatedByMe,
+                        "Created by me",
+                    );
+                    ui.selectable_value(
+                        &mut self.people_filter,
+                        FilterPeople::Unassigned,
+                        "Unassigned",
+                    );
+                });
+
                 // Search UI (Enhanced)
                 ui.horizontal(|ui| {
                     ui.label("Search:");
@@ -632,45 +2460,81 @@ impl eframe::App for TreeNotesApp {
 
                 // Issue List
                 egui::ScrollArea::vertical().show(ui, |ui| {
-                    let all_issues = self.issues.get_list();
-                    let mut display_issues: Vec<(usize, Issue)> = all_issues
-                        .iter()
-                        .enumerate()
-                        .map(|(i, issue)| (i, issue.clone()))
+                    let display_issues: Vec<(usize, Issue)> = self
+                        .filtered_indices()
+                        .into_iter()
+                        .filter_map(|i| self.issues.get(i).map(|issue| (i, issue.clone())))
                         .collect();
 
-                    display_issues.retain(|(_, issue)| self.filter_status.matches(issue.status()));
-                    if !self.query.is_empty() {
-                        let query = self.query.to_lowercase();
-                        display_issues
-                            .retain(|(_, issue)| issue.name().to_lowercase().contains(&query));
-                    }
-                    display_issues.sort_by(|a, b| b.0.cmp(&a.0));
-
                     for (id, issue) in display_issues {
-                        let is_selected = self.selected_issue_index == Some(id);
+                        let number = self.ids.number(id);
+                        let is_selected = self.selected_issue == Some(number);
                         let (icon, _color) = match issue.status() {
                             Status::Open => ("🟢", egui::Color32::GREEN),
                             Status::CloseAsCmp => ("🔴", egui::Color32::RED),
                             Status::CloseAsNotPlaned => ("⚪", egui::Color32::GRAY),
                             Status::CloseAsForked => ("🔵", egui::Color32::BLUE),
                         };
-                        let label = format!("{} #{} {}", icon, id + 1, issue.name());
+                        let label = format!("{} #{} {}", icon, number, issue.name());
 
-                        if ui.selectable_label(is_selected, label).clicked() {
-                            self.selected_issue_index = Some(id);
-                        }
+                        ui.horizontal_wrapped(|ui| {
+                            if ui.selectable_label(is_selected, label).clicked() {
+                                self.selected_issue = Some(number);
+                            }
+                            for name in issue.get_labels() {
+                                self.labels.chip(ui, &name);
+                            }
+                            for name in self.assignees_of(id) {
+                                assignees::avatar(ui, name);
+                            }
+                        });
                     }
                 });
             });
 
         // --- Central Panel (Issue Detail) ---
         egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(id) = self.selected_issue_index {
+            if let Some(id) = self.selected_index() {
                 if let Some(issue) = self.issues.get(id).cloned() {
+                    let number = self.ids.number(id);
+                    let uuid = self.ids.uuid(id).unwrap_or_default().to_string();
                     // --- Header Area ---
                     ui.horizontal(|ui| {
-                        ui.heading(format!("{} #{}", issue.name(), id + 1));
+                        match &mut self.editing_title {
+                            Some((editing, title)) if *editing == number => {
+                                let response = ui.add(
+                                    egui::TextEdit::singleline(title)
+                                        .font(egui::TextStyle::Heading)
+                                        .desired_width(300.0),
+                                );
+                                let submitted = response.lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                let title = title.trim().to_string();
+                                if (ui.button("Save").clicked() || submitted) && !title.is_empty() {
+                                    pending_op = Some(journal::Op::EditTitle {
+                                        issue: self.ids.key(id),
+                                        title,
+                                        editor: self.current_user.clone(),
+                                        date: chrono::Local::now(),
+                                    });
+                                    self.editing_title = None;
+                                } else if ui.button("Cancel").clicked() {
+                                    self.editing_title = None;
+                                }
+                            }
+                            _ => {
+                                ui.heading(format!("{} #{}", issue.name(), number));
+                                if ui.small_button("✏").on_hover_text("Edit title").clicked() {
+                                    self.editing_title = Some((number, issue.name().to_string()));
+                                }
+                            }
+                        }
+                        revisions::edited_menu(
+                            ui,
+                            self.revisions.get(&uuid, None),
+                            issue.name(),
+                            (issue.created_by(), issue.created_at()),
+                        );
 
                         let (status_text, status_bg) = match issue.status() {
                             Status::Open => (" Open ", egui::Color32::from_rgb(46, 160, 67)),
@@ -688,15 +2552,140 @@ impl eframe::App for TreeNotesApp {
                                 .strong(),
                         ));
 
-                        if issue.from_index() != 0 && issue.from_index() != usize::MAX {
-                            let parent_display_id = issue.from_index() + 1;
-                            if ui
+                        if let Some(parent) = self.ids.parent(&self.issues, id) {
+                            let parent_display_id = self.ids.number(parent);
+                            // 完全に削除された fork 元は開いても中身が無い
+                            if self
+                                .ids
+                                .uuid(parent)
+                                .is_some_and(|parent| self.trash.is_purged(parent, None))
+                            {
+                                ui.label(
+                                    egui::RichText::new(format!(
+                                        "Forked from #{} (deleted)",
+                                        parent_display_id
+                                    ))
+                                    .weak(),
+                                );
+                            } else if ui
                                 .link(format!("Forked from #{}", parent_display_id))
                                 .clicked()
                             {
                                 self.filter_status = FilterStatus::All;
-                                self.selected_issue_index = Some(issue.from_index());
+                                self.selected_issue = Some(parent_display_id);
+                            }
+                        }
+
+                        ui.menu_button("Labels ▾", |ui| {
+                            let current = issue.get_labels();
+                            let mut names: Vec<String> = self
+                                .labels
+                                .get_list()
+                                .iter()
+                                .map(|l| l.name.clone())
+                                .collect();
+                            for label in &current {
+                                if !names.contains(label) {
+                                    names.push(label.clone());
+                                }
+                            }
+                            if names.is_empty() {
+                                ui.label(egui::RichText::new("No labels yet.").weak());
+                            }
+                            for name in names {
+                                let mut checked = current.contains(&name);
+                                ui.horizontal(|ui| {
+                                    if ui.add(egui::Checkbox::without_text(&mut checked)).changed()
+                                    {
+                                        let labels = if checked {
+                                            let mut labels = current.clone();
+                                            labels.push(name.clone());
+                                            labels
+                                        } else {
+                                            current
+                                                .iter()
+                                                .filter(|l| **l != name)
+                                                .cloned()
+                                                .collect()
+                                        };
+                                        pending_op = Some(journal::Op::SetLabels {
+                                            issue: self.ids.key(id),
+                                            labels,
+                                        });
+                                    }
+                                    self.labels.chip(ui, &name);
+                                });
+                            }
+
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                let response = ui.add(
+                                    egui::TextEdit::singleline(&mut self.new_label_name)
+                                        .hint_text("New label")
+                                        .desired_width(120.0),
+                                );
+                                let name = self.new_label_name.trim().to_string();
+                                if (ui.button("Create").clicked()
+                                    || (response.lost_focus()
+                                        && ui.input(|i| i.key_pressed(egui::Key::Enter))))
+                                    && !name.is_empty()
+                                {
+                                    let mut ops = Vec::new();
+                                    if self.labels.get(&name).is_none() {
+                                        ops.push(journal::Op::DefineLabel {
+                                            label: self.labels.new_label(&name),
+                                        });
+                                    }
+                                    if !current.contains(&name) {
+                                        let mut labels = current.clone();
+                                        labels.push(name.clone());
+                                        ops.push(journal::Op::SetLabels {
+                                            issue: self.ids.key(id),
+                                            labels,
+                                        });
+                                    }
+                                    pending_batch =
+                                        Some((format!("labeled #{} {}", number, name), ops));
+                                    self.new_label_name.clear();
+                                }
+                            });
+                            if ui.button("Manage Labels…").clicked() {
+                                self.show_label_manager = true;
+                                ui.close();
+                            }
+                        });
+
+                        ui.menu_button("Assignees ▾", |ui| {
+                            let current = self.assignees_of(id).to_vec();
+                            for user in self.users.get_list() {
+                                let name = user.name().to_string();
+                                let mut checked = current.contains(&name);
+                                ui.horizontal(|ui| {
+                                    if ui.add(egui::Checkbox::without_text(&mut checked)).changed()
+                                    {
+                                        let assignees = if checked {
+                                            let mut assignees = current.clone();
+                                            assignees.push(name.clone());
+                                            assignees
+                                        } else {
+                                            current
+                                                .iter()
+                                                .filter(|n| **n != name)
+                                                .cloned()
+                                                .collect()
+                                        };
+                                        pending_op = Some(journal::Op::SetAssignees {
+                                            issue: self.ids.key(id),
+                                            assignees,
+                                        });
+                                    }
+                                    assignees::avatar(ui, &name);
+                                    ui.label(&name);
+                                });
                             }
+                        });
+                        for name in self.assignees_of(id) {
+                            assignees::avatar(ui, name);
                         }
 
                         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -719,13 +2708,7 @@ impl eframe::App for TreeNotesApp {
                                         egui::Layout::left_to_right(egui::Align::Center),
                                         |ui| {
                                             for label in issue.get_labels() {
-                                                ui.add(egui::Label::new(
-                                                    egui::RichText::new(label)
-                                                        .color(egui::Color32::BLACK)
-                                                        .background_color(
-                                                            egui::Color32::LIGHT_GRAY,
-                                                        ),
-                                                ));
+                                                self.labels.chip(ui, &label);
                                             }
                                         },
                                     );
@@ -735,6 +2718,29 @@ impl eframe::App for TreeNotesApp {
 
                     ui.separator();
 
+                    if let Some(deleted) = self.trash.get(&uuid, None) {
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "This issue is in the Trash (deleted by {} on {}).",
+                                    deleted.by.name(),
+                                    deleted.date.format("%Y-%m-%d %H:%M")
+                                ))
+                                .color(egui::Color32::RED),
+                            );
+                            if ui.button("Restore").clicked() {
+                                pending_op = Some(journal::Op::Restore {
+                                    issue: self.ids.key(id),
+                                    comment: None,
+                                });
+                            }
+                        });
+                        ui.separator();
+                    } else if self.trash.is_purged(&uuid, None) {
+                        ui.label(egui::RichText::new("This issue was permanently deleted.").weak());
+                        ui.separator();
+                    }
+
                     // --- Main Content (Single Column) ---
                     ui.allocate_ui_with_layout(
                         egui::vec2(ui.available_width(), ui.available_height()),
@@ -744,7 +2750,29 @@ impl eframe::App for TreeNotesApp {
                                 .id_salt("main_scroll")
                                 .show(ui, |ui| {
                                     // Comments
-                                    for comment in issue.comments().iter() {
+                                    for (index, comment) in issue.comments().iter().enumerate() {
+                                        if self.trash.is_deleted(&uuid, Some(index)) {
+                                            let detail = match self.trash.get(&uuid, Some(index)) {
+                                                Some(deleted) => format!(
+                                                    "Comment deleted by {} on {}",
+                                                    deleted.by.name(),
+                                                    deleted.date.format("%Y-%m-%d %H:%M")
+                                                ),
+                                                None => "Comment deleted".to_string(),
+                                            };
+                                            egui::Frame::group(ui.style()).inner_margin(8.0).show(
+                                                ui,
+                                                |ui| {
+                                                    ui.label(
+                                                        egui::RichText::new(detail)
+                                                            .weak()
+                                                            .italics(),
+                                                    );
+                                                },
+                                            );
+                                            ui.add_space(8.0);
+                                            continue;
+                                        }
                                         egui::Frame::group(ui.style()).inner_margin(8.0).show(
                                             ui,
                                             |ui| {
@@ -765,9 +2793,76 @@ impl eframe::App for TreeNotesApp {
                                                         .weak()
                                                         .size(10.0),
                                                     );
+                                                    revisions::edited_menu(
+                                                        ui,
+                                                        self.revisions.get(&uuid, Some(index)),
+                                                        &comment.text(),
+                                                        (comment.author(), &comment.date()),
+                                                    );
+                                                    if ui
+                                                        .small_button("✏")
+                                                        .on_hover_text("Edit comment")
+                                                        .clicked()
+                                                    {
+                                                        self.editing_comment = Some((
+                                                            number,
+                                                            index,
+                                                            comment.text().to_string(),
+                                                        ));
+                                                    }
+                                                    if ui
+                                                        .small_button("🗑")
+                                                        .on_hover_text("Delete comment")
+                                                        .clicked()
+                                                    {
+                                                        pending_op = Some(journal::Op::Delete {
+                                                            issue: self.ids.key(id),
+                                                            comment: Some(index),
+                                                            by: self.current_user.clone(),
+                                                            date: chrono::Local::now(),
+                                                        });
+                                                    }
                                                 });
                                                 ui.separator();
-                                                ui.label(comment.text());
+                                                match &mut self.editing_comment {
+                                                    Some((editing, i, text))
+                                                        if *editing == number && *i == index =>
+                                                    {
+                                                        ui.add(
+                                                            egui::TextEdit::multiline(text)
+                                                                .desired_width(f32::INFINITY),
+                                                        );
+                                                        let text = text.clone();
+                                                        ui.horizontal(|ui| {
+                                                            if ui
+                                                                .add_enabled(
+                                                                    !text.trim().is_empty(),
+                                                                    egui::Button::new("Save"),
+                                                                )
+                                                                .clicked()
+                                                            {
+                                                                pending_op = Some(
+                                                                    journal::Op::EditComment {
+                                                                        issue: self.ids.key(id),
+                                                                        comment: index,
+                                                                        text,
+                                                                        editor: self
+                                                                            .current_user
+                                                                            .clone(),
+                                                                        date: chrono::Local::now(),
+                                                                    },
+                                                                );
+                                                                self.editing_comment = None;
+                                                            } else if ui.button("Cancel").clicked()
+                                                            {
+                                                                self.editing_comment = None;
+                                                            }
+                                                        });
+                                                    }
+                                                    _ => {
+                                                        ui.label(comment.text());
+                                                    }
+                                                }
                                             },
                                         );
                                         ui.add_space(8.0);
@@ -778,7 +2873,7 @@ impl eframe::App for TreeNotesApp {
 
                                     ui.label(egui::RichText::new("Add a comment").strong());
 
-                                    let draft_text = self.comment_drafts.entry(id).or_default();
+                                    let draft_text = self.comment_drafts.entry(number).or_default();
                                     ui.add(
                                         egui::TextEdit::multiline(draft_text)
                                             .id(comment_id)
@@ -794,13 +2889,14 @@ impl eframe::App for TreeNotesApp {
                                         |ui| {
                                             if ui.button("Comment").clicked()
                                                 && !draft_text.is_empty()
-                                                && let Some(target_issue) = self.issues.get_mut(id)
                                             {
-                                                target_issue.comment(Comment::new(
-                                                    draft_text.clone(),
-                                                    self.current_user.clone(),
-                                                ));
-                                                draft_text.clear();
+                                                pending_op = Some(journal::Op::Comment {
+                                                    issue: self.ids.key(id),
+                                                    comment: Comment::new(
+                                                        std::mem::take(draft_text),
+                                                        self.current_user.clone(),
+                                                    ),
+                                                });
                                             }
 
                                             ui.add_space(5.0);
@@ -814,41 +2910,53 @@ impl eframe::App for TreeNotesApp {
 
                                             ui.menu_button(menu_label, |ui| {
                                                 if issue.status() == &Status::Open {
-                                                    if ui.button("Close as Completed").clicked()
-                                                        && let Some(target) =
-                                                            self.issues.get_mut(id)
-                                                    {
-                                                        target.close_as_cmp();
+                                                    if ui.button("Close as Completed").clicked() {
+                                                        pending_op =
+                                                            Some(journal::Op::CloseAsCmp {
+                                                                issue: self.ids.key(id),
+                                                            });
                                                         ui.close();
                                                     }
 
-                                                    if ui.button("Close as Not Planned").clicked()
-                                                        && let Some(target) =
-                                                            self.issues.get_mut(id)
-                                                    {
-                                                        target.close_as_not_planed();
+                                                    if ui.button("Close as Not Planned").clicked() {
+                                                        pending_op =
+                                                            Some(journal::Op::CloseAsNotPlaned {
+                                                                issue: self.ids.key(id),
+                                                            });
                                                         ui.close();
                                                     }
                                                     ui.separator();
                                                 } else {
                                                     // Closedの場合に Reopen を表示
-                                                    if ui.button("Reopen Issue").clicked()
-                                                        && let Some(target) =
-                                                            self.issues.get_mut(id)
-                                                    {
-                                                        target.reopen();
+                                                    if ui.button("Reopen Issue").clicked() {
+                                                        pending_op = Some(journal::Op::Reopen {
+                                                            issue: self.ids.key(id),
+                                                        });
                                                         ui.close();
                                                     }
                                                     ui.separator();
                                                 }
 
-                                                if ui.button("Fork this Issue").clicked()
-                                                    && let Some(new_id) = self.issues.fork(id)
-                                                {
-                                                    self.filter_status = FilterStatus::All;
-                                                    self.selected_issue_index = Some(new_id);
+                                                if ui.button("Fork this Issue").clicked() {
+                                                    pending_op = Some(journal::Op::Fork {
+                                                        issue: self.ids.key(id),
+                                                        uuid: Some(ids::new_uuid()),
+                                                    });
                                                     ui.close();
                                                 }
+
+                                                if !self.trash.is_deleted(&uuid, None) {
+                                                    ui.separator();
+                                                    if ui.button("Delete Issue").clicked() {
+                                                        pending_op = Some(journal::Op::Delete {
+                                                            issue: self.ids.key(id),
+                                                            comment: None,
+                                                            by: self.current_user.clone(),
+                                                            date: chrono::Local::now(),
+                                                        });
+                                                        ui.close();
+                                                    }
+                                                }
                                             });
                                         },
                                     );
@@ -867,9 +2975,36 @@ impl eframe::App for TreeNotesApp {
             }
         });
 
+        if let Some(op) = pending_op {
+            self.perform(op);
+        }
+        if let Some((label, ops)) = pending_batch
+            && !ops.is_empty()
+        {
+            self.perform_all(label, ops);
+        }
+        if self.filter_status != filter_before {
+            self.dirty = true;
+        }
+        self.save_if_dirty(ctx);
+
         // --- 6. Status Indicator ---
         let current_time = ctx.input(|i| i.time);
-        if current_time - self.last_save_time < 2.0 {
+        if let Some(err) = &self.last_save_error {
+            // 保存失敗は次に成功するまで表示し続ける
+            egui::Area::new(egui::Id::new("save_indicator"))
+                .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style())
+                        .inner_margin(5.0)
+                        .show(ui, |ui| {
+                            ui.label(
+                                egui::RichText::new(format!("Save failed: {}", err))
+                                    .color(egui::Color32::RED),
+                            );
+                        });
+                });
+        } else if current_time - self.last_save_time < 2.0 {
             egui::Area::new(egui::Id::new("save_indicator"))
                 .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
                 .show(ctx, |ui| {
@@ -883,6 +3018,16 @@ impl eframe::App for TreeNotesApp {
     }
 }
 
+/// `fork_notes [PATH]`
+/// PATH はトラッカーファイル、`.fork_notes` ディレクトリ、またはそれを置くリポジトリのディレクトリ
+#[cfg(not(target_arch = "wasm32"))]
+fn startup_path_from_args() -> Option<std::path::PathBuf> {
+    let arg = std::env::args_os().nth(1)?;
+    let path = std::path::PathBuf::from(arg);
+    let path = std::path::absolute(&path).unwrap_or(path);
+    Some(dir_store::resolve(&path))
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result<()> {
     let native_opts = eframe::NativeOptions {
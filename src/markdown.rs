@@ -0,0 +1,180 @@
+// ----------------------------------------------------------------------------
+// Markdown エクスポート
+// ----------------------------------------------------------------------------
+//
+// 1つの文書にまとめるか、Issue ごとに1ファイル (`0003-title.md`) に分けて書き出す。
+// ファイルを分けた場合は fork 元・fork 先へのリンクを相対パスにする。
+
+use local_issues_lib::{Issue, Issues};
+
+use crate::export::{self, OutputFile};
+use crate::ids::IssueIds;
+use crate::merge::status_label;
+use crate::trash::Trash;
+
+/// Export Markdown ウィンドウの選択
+#[derive(Default)]
+pub struct Options {
+    /// サイドパネルで絞り込まれている Issue だけを書き出す
+    pub filtered_only: bool,
+    /// Issue ごとに1ファイルにする
+    pub per_issue: bool,
+}
+
+pub fn file_name(number: usize, issue: &Issue) -> String {
+    format!("{:04}-{}.md", number, export::slug(issue.name()))
+}
+
+/// 他の Issue への参照。`linked` に含まれる (ファイルを書き出す) Issue はリンクにする。
+fn issue_ref(issues: &Issues, ids: &IssueIds, index: usize, linked: &[usize]) -> String {
+    let number = ids.number(index);
+    let Some(issue) = issues.get(index) else {
+        return format!("#{}", number);
+    };
+    let text = format!("#{} {}", number, issue.name());
+    if linked.contains(&index) {
+        format!("[{}]({})", text, file_name(number, issue))
+    } else {
+        text
+    }
+}
+
+/// Issue 1件分。`heading` は見出しのレベル (`#` の数)。
+fn render_issue(
+    issues: &Issues,
+    ids: &IssueIds,
+    trash: &Trash,
+    index: usize,
+    heading: usize,
+    linked: &[usize],
+) -> String {
+    let Some(issue) = issues.get(index) else {
+        return String::new();
+    };
+    let h = "#".repeat(heading);
+    let mut out = format!("{} #{} {}\n\n", h, ids.number(index), issue.name());
+
+    out.push_str(&format!("- **Status:** {}\n", status_label(issue.status())));
+    if !issue.get_labels().is_empty() {
+        let labels: Vec<String> = issue
+            .get_labels()
+            .iter()
+            .map(|l| format!("`{}`", l))
+            .collect();
+        out.push_str(&format!("- **Labels:** {}\n", labels.join(", ")));
+    }
+    out.push_str(&format!(
+        "- **Created:** {}\n",
+        issue.created_at().format("%Y-%m-%d %H:%M")
+    ));
+    if let Some(parent) = trash.kept_parent(issues, ids, index) {
+        out.push_str(&format!(
+            "- **Forked from:** {}\n",
+            issue_ref(issues, ids, parent, linked)
+        ));
+    }
+    let forks: Vec<String> = trash
+        .kept_children(issues, ids, index)
+        .into_iter()
+        .map(|i| issue_ref(issues, ids, i, linked))
+        .collect();
+    if !forks.is_empty() {
+        out.push_str(&format!("- **Forks:** {}\n", forks.join(", ")));
+    }
+
+    for comment in trash.kept_comments(ids, index, issue) {
+        out.push_str(&format!(
+            "\n{}# {} — {}\n\n{}\n",
+            h,
+            comment.author().name(),
+            comment.date().format("%Y-%m-%d %H:%M"),
+            comment.text().trim_end()
+        ));
+    }
+    out
+}
+
+/// `indices` の Issue を1つの文書にする
+pub fn render_document(
+    issues: &Issues,
+    ids: &IssueIds,
+    trash: &Trash,
+    indices: &[usize],
+) -> String {
+    let mut out = String::from("# Tree Notes\n\n");
+    for &i in indices {
+        if let Some(issue) = issues.get(i) {
+            out.push_str(&format!(
+                "- #{} {} ({})\n",
+                ids.number(i),
+                issue.name(),
+                status_label(issue.status())
+            ));
+        }
+    }
+    for &i in indices {
+        out.push_str("\n---\n\n");
+        out.push_str(&render_issue(issues, ids, trash, i, 2, &[]));
+    }
+    out
+}
+
+/// Issue ごとのファイルと、それらへのリンクを並べた `README.md`
+pub fn render_files(
+    issues: &Issues,
+    ids: &IssueIds,
+    trash: &Trash,
+    indices: &[usize],
+) -> Vec<OutputFile> {
+    let mut index = String::from("# Tree Notes\n\n");
+    let mut files = Vec::new();
+    for &i in indices {
+        let Some(issue) = issues.get(i) else {
+            continue;
+        };
+        index.push_str(&format!(
+            "- {} ({})\n",
+            issue_ref(issues, ids, i, indices),
+            status_label(issue.status())
+        ));
+        files.push(OutputFile {
+            path: file_name(ids.number(i), issue),
+            contents: render_issue(issues, ids, trash, i, 1, indices),
+        });
+    }
+    files.insert(
+        0,
+        OutputFile {
+            path: "README.md".to_string(),
+            contents: index,
+        },
+    );
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FilterStatus;
+    use crate::fixtures;
+    use crate::journal::Op;
+
+    #[test]
+    fn filtered_exports_leave_out_the_root() {
+        let mut app = fixtures::app_with_issues(&["write docs"]);
+        Op::CloseAsCmp {
+            issue: app.ids.key(1),
+        }
+        .apply(&mut app);
+
+        // root は Completed として作られている
+        for filter in [FilterStatus::All, FilterStatus::Completed] {
+            app.filter_status = filter;
+            let indices = app.filtered_indices();
+            assert_eq!(indices, [1]);
+            let document = render_document(&app.issues, &app.ids, &app.trash, &indices);
+            assert!(document.contains("write docs"));
+            assert!(!document.contains("root"));
+        }
+    }
+}
@@ -0,0 +1,586 @@
+// ----------------------------------------------------------------------------
+// 取り込み時のマージ
+// ----------------------------------------------------------------------------
+//
+// 取り込むデータを今のデータと突き合わせ、足りない分だけを `Op` の列にする。
+//
+// - Issue: UUID (ids.rs) が同じものを同じ Issue とみなす。UUID が一致しなければ
+//   タイトルと作成日時が同じものを同じ Issue とみなす (fork 直後などで同じものが
+//   複数ある場合は出てきた順に対応させる)。新しい Issue は UUID と fork 元を引き継ぐ
+// - Comment: 投稿者・日時・本文が同じものは既にあるとみなし、無いものだけ末尾に追加する
+// - User: 名前が同じものは同じユーザーとみなす
+// - Label: 登録簿に無い名前のものだけ追加する (色や説明が違っても今の方を残す)
+// - ゴミ箱・編集履歴: Issue の UUID とコメントの対応で付け替え、今のデータに無いものだけ追加する
+//
+// 同じ Issue で状態が食い違っている場合は衝突として一覧にし、どちらを採るかを選んでもらう。
+
+use local_issues_lib::{Issue, Status};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::TreeNotesApp;
+use crate::ids::{IssueRef, new_uuid};
+use crate::journal::Op;
+
+/// 状態が食い違っている Issue
+pub struct Conflict {
+    /// 今のデータでの index
+    pub issue: usize,
+    /// 取り込む側での index。確認している間に今のデータが変わっても変わらない。
+    pub their_issue: usize,
+    pub title: String,
+    pub ours: &'static str,
+    pub theirs: &'static str,
+    /// 取り込む側の状態にする操作。fork 済みのように再現できない状態なら None
+    pub take_theirs: Option<Op>,
+    /// 取り込む側を採るか
+    pub use_theirs: bool,
+}
+
+#[derive(Default)]
+pub struct MergePlan {
+    /// 衝突の解決に関係なく適用する操作
+    pub ops: Vec<Op>,
+    pub new_issues: usize,
+    pub new_comments: usize,
+    pub new_users: usize,
+    pub new_labels: usize,
+    /// ゴミ箱に入れる・完全に削除するもの
+    pub new_deletions: usize,
+    /// 編集履歴に足す編集
+    pub new_edits: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergePlan {
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty() && self.conflicts.is_empty()
+    }
+
+    /// 適用する操作。衝突は選ばれた方で解決する。
+    pub fn into_ops(self) -> Vec<Op> {
+        let mut ops = self.ops;
+        ops.extend(
+            self.conflicts
+                .into_iter()
+                .filter(|c| c.use_theirs)
+                .filter_map(|c| c.take_theirs),
+        );
+        ops
+    }
+
+    /// 計画し直したときに、前の計画で選ばれていた衝突の解決を引き継ぐ
+    pub fn keep_choices(&mut self, previous: &MergePlan) {
+        for conflict in &mut self.conflicts {
+            conflict.use_theirs = previous
+                .conflicts
+                .iter()
+                .any(|c| c.their_issue == conflict.their_issue && c.use_theirs);
+        }
+    }
+}
+
+pub fn status_label(status: &Status) -> &'static str {
+    match status {
+        Status::Open => "Open",
+        Status::CloseAsCmp => "Completed",
+        Status::CloseAsNotPlaned => "Not Planned",
+        Status::CloseAsForked => "Forked",
+    }
+}
+
+/// `status` にするための操作
+fn status_op(status: &Status, issue: IssueRef) -> Option<Op> {
+    match status {
+        Status::Open => Some(Op::Reopen { issue }),
+        Status::CloseAsCmp => Some(Op::CloseAsCmp { issue }),
+        Status::CloseAsNotPlaned => Some(Op::CloseAsNotPlaned { issue }),
+        Status::CloseAsForked => None,
+    }
+}
+
+fn uuid_of(issue: &IssueRef) -> Option<String> {
+    match issue {
+        IssueRef::Uuid(uuid) => Some(uuid.clone()),
+        IssueRef::Index(_) => None,
+    }
+}
+
+/// fork 元を指しているか (`from_index` が 0 / usize::MAX のものは fork ではない)
+pub fn has_parent(issue: &Issue) -> bool {
+    issue.from_index() != 0 && issue.from_index() != usize::MAX
+}
+
+/// タイトル・作成日時・同じものの中での出現順
+type IssueKey = (String, String, usize);
+
+fn issue_keys(issues: &[Issue]) -> Vec<IssueKey> {
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+    issues
+        .iter()
+        .map(|issue| {
+            let key = (issue.name().to_string(), issue.created_at().to_rfc3339());
+            let count = seen.entry(key.clone()).or_default();
+            *count += 1;
+            (key.0, key.1, *count)
+        })
+        .collect()
+}
+
+/// フィールドを1つ書き換えたものを作る。
+/// ライブラリに変更用の API が無いもの (fork 元や作成日時) のために JSON を経由する。
+/// ライブラリの形が変わって書き換えられなければエラーにする。
+pub fn with_field<T>(item: &T, field: &str, value: Value) -> Result<T, String>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut json = serde_json::to_value(item).map_err(|e| e.to_string())?;
+    let slot = json
+        .get_mut(field)
+        .ok_or_else(|| format!("no field `{}` to rewrite", field))?;
+    *slot = value;
+    serde_json::from_value(json).map_err(|e| format!("could not rewrite `{}`: {}", field, e))
+}
+
+/// fork 元の index を付け替えた Issue を作る
+pub fn with_parent(issue: &Issue, parent: usize) -> Result<Issue, String> {
+    with_field(issue, "from", Value::from(parent))
+}
+
+pub fn plan(ours: &TreeNotesApp, theirs: &TreeNotesApp) -> Result<MergePlan, String> {
+    let mut plan = MergePlan::default();
+
+    let our_users = ours.users.get_list();
+    for user in theirs.users.get_list() {
+        if !our_users.iter().any(|u| u.name() == user.name()) {
+            plan.ops.push(Op::AddUser { user: user.clone() });
+            plan.new_users += 1;
+        }
+    }
+
+    for label in theirs.labels.get_list() {
+        if ours.labels.get(&label.name).is_none() {
+            plan.ops.push(Op::DefineLabel {
+                label: label.clone(),
+            });
+            plan.new_labels += 1;
+        }
+    }
+
+    let our_issues = ours.issues.get_list();
+    let by_key: HashMap<IssueKey, usize> = issue_keys(our_issues)
+        .into_iter()
+        .enumerate()
+        .map(|(i, key)| (key, i))
+        .collect();
+    let their_issues = theirs.issues.get_list();
+
+    // 取り込む側の index -> 今のデータにある同じ Issue の index
+    let matches: Vec<Option<usize>> = issue_keys(their_issues)
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+            // 0 番目はどちらも `Issues::new()` が作る root
+            if i == 0 {
+                return Some(0);
+            }
+            theirs
+                .ids
+                .uuid(i)
+                .and_then(|uuid| ours.ids.index_of_uuid(uuid))
+                .or_else(|| by_key.get(key).copied())
+        })
+        .collect();
+    // 取り込む側の index -> 今のデータでの index と、操作で指すための参照
+    // 新しい Issue は今の末尾に順番に追加され、取り込む側の UUID を引き継ぐ
+    let mut next_index = our_issues.len();
+    let index_map: Vec<(usize, IssueRef)> = matches
+        .iter()
+        .enumerate()
+        .map(|(i, m)| match m {
+            Some(our_index) => (*our_index, ours.ids.key(*our_index)),
+            None => {
+                next_index += 1;
+                let uuid = theirs
+                    .ids
+                    .uuid(i)
+                    .map(String::from)
+                    .unwrap_or_else(new_uuid);
+                (next_index - 1, IssueRef::Uuid(uuid))
+            }
+        })
+        .collect();
+    // 取り込む側の index -> (コメントの位置 -> 今のデータでのコメントの位置)
+    let mut comment_maps: Vec<Vec<usize>> = vec![Vec::new(); their_issues.len()];
+
+    for (i, issue) in their_issues.iter().enumerate().skip(1) {
+        let their_assignees = theirs
+            .ids
+            .uuid(i)
+            .map(|uuid| theirs.assignees.get(uuid))
+            .unwrap_or_default();
+        let key = index_map[i].1.clone();
+        let Some(our_index) = matches[i] else {
+            let parent = theirs
+                .ids
+                .parent(&theirs.issues, i)
+                .and_then(|p| index_map.get(p));
+            let issue = match parent {
+                Some((parent, _)) => with_parent(issue, *parent)?,
+                None => issue.clone(),
+            };
+            plan.new_comments += issue.comments().len();
+            comment_maps[i] = (0..issue.comments().len()).collect();
+            plan.ops.push(Op::AddNewIssue {
+                issue,
+                uuid: uuid_of(&key),
+                parent: parent.and_then(|(_, parent)| uuid_of(parent)),
+            });
+            if !their_assignees.is_empty() {
+                plan.ops.push(Op::SetAssignees {
+                    issue: key,
+                    assignees: their_assignees.to_vec(),
+                });
+            }
+            plan.new_issues += 1;
+            continue;
+        };
+
+        let our_issue = &our_issues[our_index];
+        let mut next_comment = our_issue.comments().len();
+        for comment in issue.comments() {
+            let existing = our_issue.comments().iter().position(|c| {
+                c.author().name() == comment.author().name()
+                    && c.date() == comment.date()
+                    && c.text() == comment.text()
+            });
+            match existing {
+                Some(position) => comment_maps[i].push(position),
+                None => {
+                    plan.ops.push(Op::Comment {
+                        issue: key.clone(),
+                        comment: comment.clone(),
+                    });
+                    plan.new_comments += 1;
+                    comment_maps[i].push(next_comment);
+                    next_comment += 1;
+                }
+            }
+        }
+
+        // 担当者は今のデータで決まっていなければ取り込む
+        let our_assignees = ours
+            .ids
+            .uuid(our_index)
+            .map(|uuid| ours.assignees.get(uuid))
+            .unwrap_or_default();
+        if our_assignees.is_empty() && !their_assignees.is_empty() {
+            plan.ops.push(Op::SetAssignees {
+                issue: key.clone(),
+                assignees: their_assignees.to_vec(),
+            });
+        }
+
+        if our_issue.status() != issue.status() {
+            plan.conflicts.push(Conflict {
+                issue: our_index,
+                their_issue: i,
+                title: issue.name().to_string(),
+                ours: status_label(our_issue.status()),
+                theirs: status_label(issue.status()),
+                take_theirs: status_op(issue.status(), key),
+                use_theirs: false,
+            });
+        }
+    }
+
+    // 取り込む側の (Issue の UUID, コメントの位置) を今のデータでの参照にする
+    let locate = |uuid: &str, comment: Option<usize>| {
+        let i = theirs.ids.index_of_uuid(uuid).filter(|&i| i > 0)?;
+        let comment = match comment {
+            Some(c) => Some(*comment_maps[i].get(c)?),
+            None => None,
+        };
+        let (our_index, key) = &index_map[i];
+        Some((*our_index, key.clone(), comment))
+    };
+    let our_uuid = |our_index: usize| ours.ids.uuid(our_index).unwrap_or_default();
+
+    for deleted in theirs.trash.get_list() {
+        let Some((our_index, issue, comment)) = locate(&deleted.issue, deleted.comment) else {
+            continue;
+        };
+        if !ours.trash.is_deleted(our_uuid(our_index), comment) {
+            plan.ops.push(Op::Delete {
+                issue,
+                comment,
+                by: deleted.by.clone(),
+                date: deleted.date,
+            });
+            plan.new_deletions += 1;
+        }
+    }
+    for (uuid, comment) in theirs.trash.get_purged() {
+        let Some((our_index, issue, comment)) = locate(uuid, *comment) else {
+            continue;
+        };
+        if ours.trash.is_purged(our_uuid(our_index), comment) {
+            continue;
+        }
+        // 完全に削除できるのはゴミ箱に入っているものだけ
+        if ours.trash.get(our_uuid(our_index), comment).is_none() {
+            plan.ops.push(Op::Delete {
+                issue: issue.clone(),
+                comment,
+                by: ours.current_user.clone(),
+                date: chrono::Local::now(),
+            });
+        }
+        plan.ops.push(Op::Purge { issue, comment });
+        plan.new_deletions += 1;
+    }
+
+    for (uuid, comment, revisions) in theirs.revisions.get_list() {
+        let Some((our_index, issue, comment)) = locate(uuid, comment) else {
+            continue;
+        };
+        let ours_revisions = ours.revisions.get(our_uuid(our_index), comment);
+        let added: Vec<_> = revisions
+            .iter()
+            .filter(|r| {
+                !ours_revisions.iter().any(|o| {
+                    o.editor.name() == r.editor.name() && o.date == r.date && o.before == r.before
+                })
+            })
+            .cloned()
+            .collect();
+        if !added.is_empty() {
+            plan.new_edits += added.len();
+            plan.ops.push(Op::AddRevisions {
+                issue,
+                comment,
+                revisions: added,
+            });
+        }
+    }
+
+    Ok(plan)
+}
+
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// 今のデータと取り込むデータの違い (1件分)
+pub struct DiffEntry {
+    pub change: Change,
+    pub title: String,
+    pub detail: String,
+}
+
+/// Issue ごとの違いを、今のデータの順 → 取り込む側にしか無いものの順に並べる
+pub fn diff(ours: &TreeNotesApp, theirs: &TreeNotesApp) -> Vec<DiffEntry> {
+    let our_issues = ours.issues.get_list();
+    let their_issues = theirs.issues.get_list();
+    let their_by_key: HashMap<IssueKey, usize> = issue_keys(their_issues)
+        .into_iter()
+        .enumerate()
+        .map(|(i, key)| (key, i))
+        .collect();
+    let mut matched = vec![false; their_issues.len()];
+    let mut entries = Vec::new();
+
+    // 0 番目の root は比べない
+    for (our_index, (issue, key)) in our_issues
+        .iter()
+        .zip(issue_keys(our_issues))
+        .enumerate()
+        .skip(1)
+    {
+        let title = format!("#{} {}", ours.ids.number(our_index), issue.name());
+        let their_index = ours
+            .ids
+            .uuid(our_index)
+            .and_then(|uuid| theirs.ids.index_of_uuid(uuid))
+            .or_else(|| their_by_key.get(&key).copied());
+        let Some(their_index) = their_index else {
+            entries.push(DiffEntry {
+                change: Change::Removed,
+                title,
+                detail: "not in the file".to_string(),
+            });
+            continue;
+        };
+        matched[their_index] = true;
+
+        let theirs = &their_issues[their_index];
+        let mut details = Vec::new();
+        if issue.status() != theirs.status() {
+            details.push(format!(
+                "{} → {}",
+                status_label(issue.status()),
+                status_label(theirs.status())
+            ));
+        }
+        let (ours_count, theirs_count) = (issue.comments().len(), theirs.comments().len());
+        if ours_count != theirs_count {
+            details.push(format!("{} → {} comments", ours_count, theirs_count));
+        }
+        if !details.is_empty() {
+            entries.push(DiffEntry {
+                change: Change::Changed,
+                title,
+                detail: details.join(", "),
+            });
+        }
+    }
+
+    for (their_index, issue) in their_issues.iter().enumerate().skip(1) {
+        if !matched[their_index] {
+            entries.push(DiffEntry {
+                change: Change::Added,
+                title: format!("#{} {}", theirs.ids.number(their_index), issue.name()),
+                detail: format!(
+                    "{}, {} comments",
+                    status_label(issue.status()),
+                    issue.comments().len()
+                ),
+            });
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn with_field_rejects_unknown_fields() {
+        let issue = fixtures::issue(&TreeNotesApp::default(), "title", &[]);
+        assert!(with_field(&issue, "no_such_field", Value::from(1)).is_err());
+        assert_eq!(
+            with_field(&issue, "name", Value::from("renamed"))
+                .unwrap()
+                .name(),
+            "renamed"
+        );
+    }
+
+    fn merge(ours: &mut TreeNotesApp, theirs: &TreeNotesApp) {
+        for op in plan(ours, theirs).unwrap().into_ops() {
+            op.apply(ours);
+        }
+    }
+
+    /// 保存して読み直した、同じ UUID を持つ別のコピー
+    fn copy(app: &TreeNotesApp) -> TreeNotesApp {
+        serde_json::from_str(&serde_json::to_string(app).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn merging_adds_no_root_and_remaps_forks() {
+        let mut theirs = fixtures::app_with_issues(&["a", "b"]);
+        fixtures::fork(&mut theirs, 2);
+        let mut ours = fixtures::app_with_issues(&["local"]);
+        let preview = plan(&ours, &theirs).unwrap();
+        assert_eq!(preview.new_issues, 3);
+        assert!(
+            diff(&ours, &theirs)
+                .iter()
+                .all(|e| !e.title.contains("root"))
+        );
+
+        merge(&mut ours, &theirs);
+        let issues = ours.issues.get_list();
+        assert_eq!(issues.len(), 5);
+        assert_eq!(issues.iter().filter(|i| i.name() == "root").count(), 1);
+        // theirs の 2 (b) は ours では 3 になり、fork (4) はそれを指す
+        assert_eq!(issues[4].from_index(), 3);
+        assert_eq!(ours.ids.parent(&ours.issues, 4), Some(3));
+
+        // 同じものをもう一度取り込んでも何も増えない
+        assert!(plan(&ours, &theirs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn replanning_after_a_local_change_keeps_indices_and_choices() {
+        let mut ours = fixtures::app_with_issues(&["shared"]);
+        let mut theirs = copy(&ours);
+        let user = theirs.current_user.clone();
+        Op::CloseAsCmp {
+            issue: theirs.ids.key(1),
+        }
+        .apply(&mut theirs);
+        fixtures::add_issue(&mut theirs, "new");
+        Op::SetAssignees {
+            issue: theirs.ids.key(2),
+            assignees: vec![user.name()],
+        }
+        .apply(&mut theirs);
+
+        let mut preview = plan(&ours, &theirs).unwrap();
+        assert_eq!(preview.conflicts.len(), 1);
+        preview.conflicts[0].use_theirs = true;
+
+        // 確認している間に Issue を追加する
+        fixtures::add_issue(&mut ours, "while previewing");
+
+        let mut fresh = plan(&ours, &theirs).unwrap();
+        fresh.keep_choices(&preview);
+        for op in fresh.into_ops() {
+            op.apply(&mut ours);
+        }
+        assert_eq!(ours.issues.get(1).unwrap().status(), &Status::CloseAsCmp);
+        assert_eq!(ours.issues.get(3).unwrap().name(), "new");
+        assert_eq!(ours.assignees_of(3), [user.name()]);
+        assert!(ours.assignees_of(2).is_empty());
+    }
+
+    #[test]
+    fn trash_and_edits_follow_the_issue_to_its_new_position() {
+        let mut ours = fixtures::app_with_issues(&["shared"]);
+        let mut theirs = copy(&ours);
+        fixtures::add_issue(&mut ours, "local");
+        let index = fixtures::add_issue(&mut theirs, "new");
+        let user = theirs.current_user.clone();
+        for op in [
+            Op::EditTitle {
+                issue: theirs.ids.key(index),
+                title: "renamed".to_string(),
+                editor: user.clone(),
+                date: chrono::Local::now(),
+            },
+            Op::Delete {
+                issue: theirs.ids.key(index),
+                comment: Some(0),
+                by: user.clone(),
+                date: chrono::Local::now(),
+            },
+            Op::Delete {
+                issue: theirs.ids.key(1),
+                comment: None,
+                by: user,
+                date: chrono::Local::now(),
+            },
+        ] {
+            op.apply(&mut theirs);
+        }
+
+        let preview = plan(&ours, &theirs).unwrap();
+        assert_eq!((preview.new_deletions, preview.new_edits), (2, 1));
+        merge(&mut ours, &theirs);
+        // 取り込む側の 2 番目は ours では 3 番目になる
+        let uuid = ours.ids.uuid(3).unwrap();
+        assert_eq!(ours.issues.get(3).unwrap().name(), "renamed");
+        assert_eq!(ours.revisions.get(uuid, None)[0].before, "new");
+        assert!(ours.trash.get(uuid, Some(0)).is_some());
+        assert!(ours.trash.is_deleted_at(&ours.ids, 1, None));
+        assert!(!ours.trash.is_deleted_at(&ours.ids, 2, None));
+        assert!(plan(&ours, &theirs).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+// ----------------------------------------------------------------------------
+// タイトルとコメントの編集履歴
+// ----------------------------------------------------------------------------
+//
+// ライブラリの Issue / Comment は今の本文しか持たないので、編集前の本文・編集した人・日時を
+// ここに Issue の UUID (ids.rs) とコメントの位置ごとに残す。GitHub の "edited" のように、どの版でも見られる。
+
+use chrono::{DateTime, Local};
+use eframe::egui;
+use local_issues_lib::user::User;
+use serde::{Deserialize, Serialize};
+
+/// 1回の編集
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Revision {
+    /// 編集前の本文
+    pub before: String,
+    pub editor: User,
+    pub date: DateTime<Local>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Log {
+    issue: String,
+    /// None ならタイトル
+    comment: Option<usize>,
+    revisions: Vec<Revision>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Revisions {
+    logs: Vec<Log>,
+}
+
+impl Revisions {
+    /// 古い順の編集。`comment` が None ならタイトルの編集。
+    pub fn get(&self, issue: &str, comment: Option<usize>) -> &[Revision] {
+        self.logs
+            .iter()
+            .find(|l| l.issue == issue && l.comment == comment)
+            .map(|l| l.revisions.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// `(Issue の UUID, コメントの位置, 編集)` の一覧
+    pub fn get_list(&self) -> impl Iterator<Item = (&str, Option<usize>, &[Revision])> {
+        self.logs
+            .iter()
+            .map(|l| (l.issue.as_str(), l.comment, l.revisions.as_slice()))
+    }
+
+    pub fn record(&mut self, issue: &str, comment: Option<usize>, revision: Revision) {
+        match self
+            .logs
+            .iter_mut()
+            .find(|l| l.issue == issue && l.comment == comment)
+        {
+            Some(log) => log.revisions.push(revision),
+            None => self.logs.push(Log {
+                issue: issue.to_string(),
+                comment,
+                revisions: vec![revision],
+            }),
+        }
+    }
+
+    /// コメントの履歴を消す
+    pub fn remove_comment(&mut self, issue: &str, comment: usize) {
+        self.logs
+            .retain(|l| !(l.issue == issue && l.comment == Some(comment)));
+    }
+
+    /// Issue のタイトルと全てのコメントの履歴を消す
+    pub fn remove_issue(&mut self, issue: &str) {
+        self.logs.retain(|l| l.issue != issue);
+    }
+}
+
+/// 各版の (書いた人, 日時, 本文) を新しい順に並べる。
+/// 最後の要素は最初に書かれた版で、書いた人と日時は `created` を使う。
+fn versions<'a>(
+    revisions: &'a [Revision],
+    current: &'a str,
+    created: (&'a User, &'a DateTime<Local>),
+) -> Vec<(&'a User, &'a DateTime<Local>, &'a str)> {
+    let mut versions = Vec::new();
+    for (i, revision) in revisions.iter().enumerate().rev() {
+        let after = revisions
+            .get(i + 1)
+            .map(|r| r.before.as_str())
+            .unwrap_or(current);
+        versions.push((&revision.editor, &revision.date, after));
+    }
+    let original = revisions
+        .first()
+        .map(|r| r.before.as_str())
+        .unwrap_or(current);
+    versions.push((created.0, created.1, original));
+    versions
+}
+
+/// "edited ▾" のドロップダウン。開くと各版を誰がいつ書いたかが並び、選ぶと本文が見られる。
+/// 編集されていなければ何も出さない。
+pub fn edited_menu(
+    ui: &mut egui::Ui,
+    revisions: &[Revision],
+    current: &str,
+    created: (&User, &DateTime<Local>),
+) {
+    let Some(last) = revisions.last() else {
+        return;
+    };
+    ui.menu_button(
+        egui::RichText::new(format!("edited by {} ▾", last.editor.name()))
+            .weak()
+            .size(10.0),
+        |ui| {
+            ui.set_max_width(400.0);
+            let versions = versions(revisions, current, created);
+            let original = versions.len() - 1;
+            for (i, (user, date, text)) in versions.into_iter().enumerate() {
+                let action = if i == original { "created" } else { "edited" };
+                egui::CollapsingHeader::new(format!(
+                    "{} {} {}",
+                    user.name(),
+                    action,
+                    date.format("%Y-%m-%d %H:%M")
+                ))
+                .id_salt(i)
+                .show(ui, |ui| {
+                    ui.label(text);
+                });
+            }
+        },
+    );
+}
@@ -0,0 +1,293 @@
+// ----------------------------------------------------------------------------
+// 保存ファイルのスキーマバージョンとマイグレーション
+// ----------------------------------------------------------------------------
+//
+// 保存されるJSONのトップレベルには `schema_version` が入る。
+// 古いファイルは `migrate_from` を順番に適用して `CURRENT_VERSION` まで引き上げてから
+// `TreeNotesApp` にデシリアライズする。
+
+use local_issues_lib::Issues;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+use crate::TreeNotesApp;
+use crate::assignees::Assignees;
+use crate::ids::IssueIds;
+use crate::labels::Labels;
+use crate::revisions::Revisions;
+use crate::trash::Trash;
+
+/// 現在のスキーマバージョン。保存形式を変えたら上げて `migrate_from` に手順を足す。
+pub const CURRENT_VERSION: u32 = 7;
+pub const VERSION_KEY: &str = "schema_version";
+
+/// これが無いファイルは壊れているとみなす(`serde(default)` で空データにされるのを防ぐ)
+const REQUIRED_KEYS: [&str; 2] = ["issues", "users"];
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    /// JSONとして読めない、または `TreeNotesApp` の形になっていない
+    Parse(serde_json::Error),
+    /// このバージョンのアプリより新しい形式で保存されている
+    TooNew {
+        found: u32,
+    },
+    Invalid(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "could not read file: {}", e),
+            LoadError::Parse(e) => write!(f, "could not parse data: {}", e),
+            LoadError::TooNew { found } => write!(
+                f,
+                "data was saved with schema version {}, but this build only supports up to {}. \
+                 Please update the app.",
+                found, CURRENT_VERSION
+            ),
+            LoadError::Invalid(msg) => write!(f, "invalid data: {}", msg),
+        }
+    }
+}
+
+impl LoadError {
+    /// ファイルの中身自体が壊れている場合 true。
+    /// 読み込み権限がない・新しいバージョンのファイル、といった場合は中身に問題はない。
+    pub fn is_corrupt(&self) -> bool {
+        matches!(self, LoadError::Parse(_) | LoadError::Invalid(_))
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(e: serde_json::Error) -> Self {
+        LoadError::Parse(e)
+    }
+}
+
+fn version_of(value: &Value) -> Result<u32, LoadError> {
+    match value.get(VERSION_KEY) {
+        // バージョン導入前のファイル
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| LoadError::Invalid(format!("`{}` is not a number", VERSION_KEY))),
+    }
+}
+
+/// `key` が無ければ `T` の空の値を入れる
+fn insert_default<T: Default + Serialize>(value: &mut Value, key: &str) -> Result<(), LoadError> {
+    if let Some(object) = value.as_object_mut()
+        && !object.contains_key(key)
+    {
+        object.insert(key.to_string(), serde_json::to_value(T::default())?);
+    }
+    Ok(())
+}
+
+/// Issue に番号と UUID を振り、ゴミ箱と編集履歴が位置で指していた Issue を UUID に置き換える
+fn assign_ids(value: &mut Value) -> Result<(), LoadError> {
+    let issues: Issues = serde_json::from_value(value["issues"].clone())?;
+    let mut ids = IssueIds::default();
+    ids.sync(&issues);
+    let to_uuid = |slot: Option<&mut Value>| {
+        let slot = slot.ok_or_else(|| LoadError::Invalid("an entry has no issue".to_string()))?;
+        let uuid = slot
+            .as_u64()
+            .and_then(|index| ids.uuid(index as usize))
+            .ok_or_else(|| LoadError::Invalid(format!("no issue at position {}", slot)))?;
+        *slot = Value::from(uuid);
+        Ok::<(), LoadError>(())
+    };
+    if let Some(deleted) = value
+        .pointer_mut("/trash/deleted")
+        .and_then(Value::as_array_mut)
+    {
+        for entry in deleted {
+            to_uuid(entry.get_mut("issue"))?;
+        }
+    }
+    if let Some(purged) = value
+        .pointer_mut("/trash/purged")
+        .and_then(Value::as_array_mut)
+    {
+        for entry in purged {
+            to_uuid(entry.get_mut(0))?;
+        }
+    }
+    if let Some(logs) = value.get_mut("revisions").and_then(Value::as_array_mut) {
+        for log in logs {
+            to_uuid(log.get_mut("issue"))?;
+        }
+    }
+    value["ids"] = serde_json::to_value(&ids)?;
+    Ok(())
+}
+
+/// `version` から `version + 1` への変換
+fn migrate_from(version: u32, value: &mut Value) -> Result<(), LoadError> {
+    match version {
+        // v0 -> v1: 中身は同じで、バージョンフィールドを付けるだけ
+        0 => {}
+        // v1 -> v2: ジャーナル。まだ何も適用していない
+        1 => insert_default::<u64>(value, "journal_seq")?,
+        // v2 -> v3: ラベルの色と説明
+        2 => insert_default::<Labels>(value, "labels")?,
+        // v3 -> v4: 編集履歴
+        3 => insert_default::<Revisions>(value, "revisions")?,
+        // v4 -> v5: ゴミ箱
+        4 => insert_default::<Trash>(value, "trash")?,
+        // v5 -> v6: 番号と UUID。ゴミ箱と編集履歴は位置ではなく UUID で Issue を指す
+        5 => assign_ids(value)?,
+        // v6 -> v7: 担当者
+        6 => insert_default::<Assignees>(value, "assignees")?,
+        _ => unreachable!("no migration from schema version {}", version),
+    }
+    value[VERSION_KEY] = Value::from(version + 1);
+    Ok(())
+}
+
+/// 保存済みのJSONを現在のスキーマまでマイグレーションして読み込む
+pub fn load(json: &str) -> Result<TreeNotesApp, LoadError> {
+    from_value(serde_json::from_str(json)?)
+}
+
+/// 保存先ごとに組み立てたJSONを現在のスキーマまでマイグレーションして読み込む
+pub fn from_value(mut value: Value) -> Result<TreeNotesApp, LoadError> {
+    let Some(object) = value.as_object() else {
+        return Err(LoadError::Invalid("top level is not an object".to_string()));
+    };
+    for key in REQUIRED_KEYS {
+        if !object.contains_key(key) {
+            return Err(LoadError::Invalid(format!("missing `{}`", key)));
+        }
+    }
+
+    let version = version_of(&value)?;
+    if version > CURRENT_VERSION {
+        return Err(LoadError::TooNew { found: version });
+    }
+    for v in version..CURRENT_VERSION {
+        migrate_from(v, &mut value)?;
+    }
+
+    let mut app = serde_json::from_value::<TreeNotesApp>(value)?;
+    // 番号を持つ前のデータ
+    app.ids.sync(&app.issues);
+    Ok(app)
+}
+
+/// 取り込み時に表示する、エラーの位置と該当行を含めた説明
+pub fn error_report(json: &str, error: &LoadError) -> String {
+    let mut message = error.to_string();
+    let mut position = match error {
+        LoadError::Parse(e) if e.line() > 0 => Some((e.line(), e.column())),
+        _ => None,
+    };
+    // `Value` から変換したときのエラー (型が違う等) は位置を持たないので、直接読み直して位置を得る
+    if position.is_none()
+        && matches!(error, LoadError::Parse(_))
+        && let Err(e) = serde_json::from_str::<TreeNotesApp>(json)
+        && e.line() > 0
+    {
+        position = Some((e.line(), e.column()));
+        message = LoadError::Parse(e).to_string();
+    }
+
+    let Some((line, column)) = position else {
+        return message;
+    };
+    let Some(text) = json.lines().nth(line - 1) else {
+        return message;
+    };
+    let number = line.to_string();
+    format!(
+        "{}\n\n{} | {}\n{} | {}^",
+        message,
+        number,
+        text,
+        " ".repeat(number.len()),
+        " ".repeat(column.saturating_sub(1))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// バージョン導入直後 (v1) の形のファイル
+    fn v1_file() -> Value {
+        let app = fixtures::app_with_issues(&["old"]);
+        serde_json::json!({
+            VERSION_KEY: 1,
+            "issues": app.issues,
+            "users": app.users,
+        })
+    }
+
+    #[test]
+    fn old_files_get_every_new_field() {
+        let mut value = v1_file();
+        for v in 1..CURRENT_VERSION {
+            migrate_from(v, &mut value).unwrap();
+        }
+        assert_eq!(value[VERSION_KEY], CURRENT_VERSION);
+        for key in [
+            "journal_seq",
+            "labels",
+            "revisions",
+            "trash",
+            "ids",
+            "assignees",
+        ] {
+            assert!(value.get(key).is_some(), "`{}` was not added", key);
+        }
+
+        let app = load(&v1_file().to_string()).unwrap();
+        assert_eq!(app.journal_seq, 0);
+        assert!(app.ids.uuid(1).is_some());
+    }
+
+    #[test]
+    fn trash_and_revisions_move_from_positions_to_uuids() {
+        let mut value = v1_file();
+        for v in 1..5 {
+            migrate_from(v, &mut value).unwrap();
+        }
+        let entry = serde_json::json!({
+            "issue": 1,
+            "comment": null,
+            "by": TreeNotesApp::default().current_user,
+            "date": chrono::Local::now(),
+        });
+        value["trash"]["deleted"] = Value::from(vec![entry]);
+        value["trash"]["purged"] = serde_json::json!([[1, 0]]);
+        value["revisions"] = serde_json::json!([{ "issue": 1, "comment": null, "revisions": [] }]);
+
+        let app = from_value(value).unwrap();
+        let uuid = app.ids.uuid(1).unwrap();
+        assert!(app.trash.get(uuid, None).is_some());
+        assert!(app.trash.is_purged(uuid, Some(0)));
+        assert_eq!(app.revisions.get_list().next().unwrap().0, uuid);
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let mut value = v1_file();
+        value[VERSION_KEY] = Value::from(CURRENT_VERSION + 1);
+        assert!(matches!(
+            load(&value.to_string()),
+            Err(LoadError::TooNew { .. })
+        ));
+    }
+}
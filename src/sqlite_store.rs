@@ -0,0 +1,407 @@
+// ----------------------------------------------------------------------------
+// SQLite の保存先 (`sqlite` feature)
+// ----------------------------------------------------------------------------
+//
+// Issue と Comment を行単位で持ち、前回書き込んだ内容と比べて変わった行だけを書き込む。
+//
+// - meta:     schema_version / users / labels / revisions / trash / ids / assignees /
+//             current_user / filter_status (値はJSON)
+// - issues:   コメントを除いた Issue のJSON
+// - comments: Issue ごと、位置ごとの Comment のJSON
+// - journal:  操作ジャーナル (journal.rs)
+//
+// Issue のコメントは local_issues_lib のシリアライズでは `comment` キーに入る。
+// 読み込みでは行ごとに覚えたキーに戻す。
+
+use local_issues_lib::Issue;
+use rusqlite::{Connection, ErrorCode, params};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::schema::{self, LoadError};
+use crate::storage::{self, Store};
+use crate::{TreeNotesApp, backup, journal};
+
+/// SQLite として開くファイルの拡張子
+pub const EXTENSIONS: [&str; 2] = ["sqlite", "db"];
+
+pub fn is_sqlite_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext))
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS issues (
+        idx          INTEGER PRIMARY KEY,
+        data         TEXT NOT NULL,
+        comments_key TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS comments (
+        issue_idx INTEGER NOT NULL,
+        position  INTEGER NOT NULL,
+        data      TEXT NOT NULL,
+        PRIMARY KEY (issue_idx, position)
+    );
+    CREATE TABLE IF NOT EXISTS journal (
+        seq  INTEGER PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+";
+
+/// Issue のJSONでコメントが入っているキー
+const COMMENTS_KEY: &str = "comment";
+
+/// DB に書き込み済みの内容。これと比べて差分だけ書く。
+#[derive(Default)]
+struct Written {
+    meta: HashMap<String, String>,
+    issues: Vec<(String, String)>,
+    comments: Vec<Vec<String>>,
+}
+
+pub struct SqliteStore {
+    path: PathBuf,
+    written: RefCell<Option<Written>>,
+}
+
+impl SqliteStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            written: RefCell::new(None),
+        }
+    }
+
+    fn connect(&self) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+}
+
+fn to_load_error(e: rusqlite::Error) -> LoadError {
+    match e.sqlite_error_code() {
+        Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) => {
+            LoadError::Invalid(e.to_string())
+        }
+        _ => LoadError::Io(std::io::Error::other(e)),
+    }
+}
+
+/// Issue のJSONをコメント部分とそれ以外に分ける
+fn split_issue(issue: &Issue) -> Result<(String, String, Vec<String>), String> {
+    let mut value = serde_json::to_value(issue).map_err(|e| e.to_string())?;
+    let comments = value
+        .as_object_mut()
+        .and_then(|o| o.remove(COMMENTS_KEY))
+        .ok_or_else(|| format!("issue has no `{}` field", COMMENTS_KEY))?;
+    let comments = match comments {
+        Value::Array(comments) => comments,
+        _ => return Err(format!("`{}` is not a list", COMMENTS_KEY)),
+    };
+    let comments = comments
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<serde_json::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    let data = serde_json::to_string(&value).map_err(|e| e.to_string())?;
+    Ok((data, COMMENTS_KEY.to_string(), comments))
+}
+
+fn join_issue(data: &str, comments_key: &str, comments: &[String]) -> Result<Issue, LoadError> {
+    let mut value: Value = serde_json::from_str(data)?;
+    let comments = comments
+        .iter()
+        .map(|c| serde_json::from_str(c))
+        .collect::<serde_json::Result<Vec<Value>>>()?;
+    if let Some(o) = value.as_object_mut() {
+        o.insert(comments_key.to_string(), Value::Array(comments));
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+fn meta_entries(app: &TreeNotesApp) -> serde_json::Result<Vec<(&'static str, String)>> {
+    Ok(vec![
+        ("schema_version", schema::CURRENT_VERSION.to_string()),
+        ("users", serde_json::to_string(&app.users)?),
+        ("labels", serde_json::to_string(&app.labels)?),
+        ("revisions", serde_json::to_string(&app.revisions)?),
+        ("trash", serde_json::to_string(&app.trash)?),
+        ("ids", serde_json::to_string(&app.ids)?),
+        ("assignees", serde_json::to_string(&app.assignees)?),
+        ("current_user", serde_json::to_string(&app.current_user)?),
+        ("filter_status", serde_json::to_string(&app.filter_status)?),
+        ("journal_seq", app.journal_seq.to_string()),
+    ])
+}
+
+impl Store for SqliteStore {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<Option<TreeNotesApp>, LoadError> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let conn = self.connect().map_err(to_load_error)?;
+        let mut written = Written::default();
+
+        let mut stmt = conn
+            .prepare("SELECT key, value FROM meta")
+            .map_err(to_load_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(to_load_error)?;
+        for row in rows {
+            let (key, value) = row.map_err(to_load_error)?;
+            written.meta.insert(key, value);
+        }
+
+        // meta の値は全てJSONなので、JsonFileStore と同じ形に組み立ててマイグレーションに通す
+        let mut value = serde_json::Map::new();
+        for (key, json) in &written.meta {
+            value.insert(key.clone(), serde_json::from_str(json)?);
+        }
+
+        let mut issue_stmt = conn
+            .prepare("SELECT idx, data, comments_key FROM issues ORDER BY idx")
+            .map_err(to_load_error)?;
+        let mut comment_stmt = conn
+            .prepare("SELECT data FROM comments WHERE issue_idx = ?1 ORDER BY position")
+            .map_err(to_load_error)?;
+        let issue_rows = issue_stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, usize>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(to_load_error)?;
+        let mut stored = Vec::new();
+        for row in issue_rows {
+            let (idx, data, comments_key) = row.map_err(to_load_error)?;
+            let comments = comment_stmt
+                .query_map([idx], |row| row.get::<_, String>(0))
+                .map_err(to_load_error)?
+                .collect::<rusqlite::Result<Vec<String>>>()
+                .map_err(to_load_error)?;
+            stored.push((idx, join_issue(&data, &comments_key, &comments)?));
+            written.issues.push((data, comments_key));
+            written.comments.push(comments);
+        }
+        let issues = storage::issues_in_order(stored)?;
+        value.insert("issues".to_string(), serde_json::to_value(&issues)?);
+        let app = schema::from_value(Value::Object(value))?;
+
+        *self.written.borrow_mut() = Some(written);
+        Ok(Some(app))
+    }
+
+    fn save(&mut self, app: &TreeNotesApp) -> Result<(), String> {
+        let err = |e: rusqlite::Error| format!("{}: {}", self.path.display(), e);
+        let mut conn = self.connect().map_err(err)?;
+        let tx = conn.transaction().map_err(err)?;
+        // コミットできたときだけ戻す。失敗したら DB と合っているか分からないので、
+        // 読み込まずに保存する場合と同じく次は全部書き直す。
+        let previous = self.written.borrow_mut().take();
+        if previous.is_none() {
+            tx.execute_batch("DELETE FROM meta; DELETE FROM issues; DELETE FROM comments;")
+                .map_err(err)?;
+        }
+        let mut written = previous.unwrap_or_default();
+
+        for (key, value) in meta_entries(app).map_err(|e| e.to_string())? {
+            if written.meta.get(key) != Some(&value) {
+                tx.execute(
+                    "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                    params![key, value],
+                )
+                .map_err(err)?;
+                written.meta.insert(key.to_string(), value);
+            }
+        }
+
+        let issues = app.issues.get_list();
+        for (idx, issue) in issues.iter().enumerate() {
+            let (data, comments_key, comments) = split_issue(issue)?;
+
+            let row = (data, comments_key);
+            if written.issues.get(idx) != Some(&row) {
+                tx.execute(
+                    "INSERT OR REPLACE INTO issues (idx, data, comments_key) VALUES (?1, ?2, ?3)",
+                    params![idx, row.0, row.1],
+                )
+                .map_err(err)?;
+                if idx < written.issues.len() {
+                    written.issues[idx] = row;
+                } else {
+                    written.issues.push(row);
+                    written.comments.push(Vec::new());
+                }
+            }
+
+            let written_comments = &mut written.comments[idx];
+            for (position, comment) in comments.iter().enumerate() {
+                if written_comments.get(position) != Some(comment) {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO comments (issue_idx, position, data) \
+                         VALUES (?1, ?2, ?3)",
+                        params![idx, position, comment],
+                    )
+                    .map_err(err)?;
+                }
+            }
+            if written_comments.len() > comments.len() {
+                tx.execute(
+                    "DELETE FROM comments WHERE issue_idx = ?1 AND position >= ?2",
+                    params![idx, comments.len()],
+                )
+                .map_err(err)?;
+            }
+            *written_comments = comments;
+        }
+
+        if written.issues.len() > issues.len() {
+            tx.execute("DELETE FROM issues WHERE idx >= ?1", [issues.len()])
+                .map_err(err)?;
+            tx.execute("DELETE FROM comments WHERE issue_idx >= ?1", [issues.len()])
+                .map_err(err)?;
+            written.issues.truncate(issues.len());
+            written.comments.truncate(issues.len());
+        }
+
+        tx.commit().map_err(err)?;
+        *self.written.borrow_mut() = Some(written);
+        Ok(())
+    }
+
+    fn append_journal(&mut self, entry: &journal::Entry) -> Result<(), String> {
+        let err = |e: rusqlite::Error| format!("{}: {}", self.path.display(), e);
+        let data = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        self.connect()
+            .and_then(|conn| {
+                conn.execute(
+                    "INSERT INTO journal (seq, data) VALUES (?1, ?2)",
+                    params![entry.seq, data],
+                )
+            })
+            .map(|_| ())
+            .map_err(err)
+    }
+
+    fn read_journal(&self) -> Result<Vec<journal::Entry>, String> {
+        let err = |e: rusqlite::Error| format!("{}: {}", self.path.display(), e);
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let conn = self.connect().map_err(err)?;
+        let mut stmt = conn
+            .prepare("SELECT data FROM journal ORDER BY seq")
+            .map_err(err)?;
+        let lines = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(err)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(err)?;
+        Ok(journal::parse_lines(&lines.join("\n")))
+    }
+
+    fn quarantine(&mut self) -> Result<String, String> {
+        backup::quarantine(&self.path)
+            .map(|p| p.display().to_string())
+            .map_err(|e| format!("failed to move the file aside: {}", e))
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+/// 今のトラッカーを SQLite ファイルに書き出す (data.json からの移行用)
+pub fn export(app: &TreeNotesApp, path: &Path) -> Result<(), String> {
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+    SqliteStore::new(path.to_path_buf()).save(app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, app_with_issues};
+    use local_issues_lib::Comment;
+
+    fn temp_path() -> PathBuf {
+        fixtures::temp_path(".sqlite")
+    }
+
+    #[test]
+    fn split_and_join_keep_the_comments() {
+        let app = app_with_issues(&["a"]);
+        let issue = &app.issues.get_list()[1];
+        let (data, key, comments) = split_issue(issue).unwrap();
+        assert_eq!(key, COMMENTS_KEY);
+        assert_eq!(comments.len(), 1);
+        let joined = join_issue(&data, &key, &comments).unwrap();
+        assert_eq!(joined.comments()[0].text(), "a");
+    }
+
+    #[test]
+    fn saving_again_writes_only_the_changed_rows() {
+        let path = temp_path();
+        let mut app = app_with_issues(&["a", "b"]);
+        let mut store = SqliteStore::new(path.clone());
+        store.save(&app).unwrap();
+
+        // 変えていない Issue の行を直接書き換えておき、次の保存で上書きされないことを見る
+        let conn = Connection::open(&path).unwrap();
+        let (data, _, _) = split_issue(&app.issues.get_list()[1]).unwrap();
+        let marked = data.replacen("\"a\"", "\"untouched\"", 1);
+        conn.execute("UPDATE issues SET data = ?1 WHERE idx = 1", [&marked])
+            .unwrap();
+
+        let user = app.current_user.clone();
+        app.issues
+            .get_mut(2)
+            .unwrap()
+            .comment(Comment::new("more", user));
+        fixtures::add_issue(&mut app, "c");
+        store.save(&app).unwrap();
+
+        let loaded = SqliteStore::new(path.clone()).load().unwrap().unwrap();
+        let list = loaded.issues.get_list();
+        assert_eq!(list.len(), 4);
+        assert_eq!(list[1].name(), "untouched");
+        assert_eq!(list[2].comments().len(), 2);
+        assert_eq!(list[3].name(), "c");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn saving_without_loading_rewrites_everything() {
+        let path = temp_path();
+        SqliteStore::new(path.clone())
+            .save(&app_with_issues(&["a", "b"]))
+            .unwrap();
+        SqliteStore::new(path.clone())
+            .save(&app_with_issues(&["c"]))
+            .unwrap();
+
+        let loaded = SqliteStore::new(path.clone()).load().unwrap().unwrap();
+        let list = loaded.issues.get_list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[1].name(), "c");
+        let _ = std::fs::remove_file(path);
+    }
+}
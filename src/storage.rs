@@ -0,0 +1,452 @@
+// ----------------------------------------------------------------------------
+// 保存先の抽象化
+// ----------------------------------------------------------------------------
+//
+// `TreeNotesApp` は `Box<dyn Store>` を1つ持ち、読み込み・保存・スナップショットは全てそれを通す。
+//
+// - `JsonFileStore`: data.json 1ファイル (+ backups/ のローテーション)
+// - `DirectoryStore`: リポジトリ内の `.fork_notes/`
+// - `SqliteStore`: `.sqlite` / `.db` ファイル (`sqlite` feature, sqlite_store.rs)
+// - `EframeStore`: wasm版。eframe と同じ browser の localStorage のキー
+// - `MemoryStore`: ディスクに書かない。保存先が決められない場合やテスト用
+
+use chrono::{DateTime, Local};
+#[cfg(not(target_arch = "wasm32"))]
+use local_issues_lib::{Issue, Issues};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+use crate::{TreeNotesApp, journal, schema, schema::LoadError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{backup, dir_store};
+
+/// Restore ウィンドウに表示する1件分の情報
+pub struct SnapshotInfo {
+    /// `Store::load_snapshot` に渡す識別子
+    pub id: String,
+    pub modified: DateTime<Local>,
+    /// 読み込めなかった場合は None
+    pub issue_count: Option<usize>,
+    pub user_count: Option<usize>,
+}
+
+pub trait Store: Send {
+    /// メッセージに出す保存先の説明
+    fn describe(&self) -> String;
+
+    /// まだ何も保存されていなければ Ok(None)
+    fn load(&self) -> Result<Option<TreeNotesApp>, LoadError>;
+
+    fn save(&mut self, app: &TreeNotesApp) -> Result<(), String>;
+
+    /// 操作ジャーナルに1件追記する。ジャーナルを持たない保存先では何もしない。
+    fn append_journal(&mut self, _entry: &journal::Entry) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// 古い順
+    fn read_journal(&self) -> Result<Vec<journal::Entry>, String> {
+        Ok(Vec::new())
+    }
+
+    /// 新しい順
+    fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        Vec::new()
+    }
+
+    /// 今保存されている内容のスナップショットを取る
+    fn create_snapshot(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn load_snapshot(&self, _id: &str) -> Result<TreeNotesApp, String> {
+        Err(format!("{} does not keep snapshots", self.describe()))
+    }
+
+    /// 読み込めなかったデータを退避し、退避先の説明を返す。
+    /// 退避できない保存先では Err を返し、その場合 "Start Fresh" は出さない。
+    fn quarantine(&mut self) -> Result<String, String> {
+        Err(format!("{} can't be moved aside", self.describe()))
+    }
+
+    /// ファイルシステム上の場所。ワークスペースの切り替えに使う。
+    #[cfg(not(target_arch = "wasm32"))]
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// 一時ファイルに書き込んでfsyncした後、renameで差し替える。
+/// 途中でクラッシュしても元のファイルか新しいファイルのどちらかが必ず残る。
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::fs;
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+
+    // renameをディスクに反映させるため親ディレクトリもfsyncする
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Issue を1件ずつ分けて保存する保存先 (dir_store, sqlite_store) で、読み込んだ Issue を並べ直す。
+/// `stored` は (位置, Issue) を位置の順に並べたもの。
+/// 保存したものには root Issue (位置 0) も入っているので、空の一覧に読み込む。
+/// 位置が飛んでいると fork 元の参照がずれるので読み込まない。
+#[cfg(not(target_arch = "wasm32"))]
+pub fn issues_in_order(
+    stored: impl IntoIterator<Item = (usize, Issue)>,
+) -> Result<Issues, LoadError> {
+    let mut issues = Issues::default();
+    for (i, (position, issue)) in stored.into_iter().enumerate() {
+        if position != i {
+            return Err(LoadError::Invalid(format!("issue #{} is missing", i + 1)));
+        }
+        issues.add_new_issue(issue);
+    }
+    Ok(issues)
+}
+
+/// パスに合わせて保存先を選ぶ
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open(path: PathBuf) -> Box<dyn Store> {
+    if dir_store::is_tracker_dir(&path) {
+        return Box::new(DirectoryStore { dir: path });
+    }
+    #[cfg(feature = "sqlite")]
+    if crate::sqlite_store::is_sqlite_path(&path) {
+        return Box::new(crate::sqlite_store::SqliteStore::new(path));
+    }
+    Box::new(JsonFileStore { path })
+}
+
+// ----------------------------------------------------------------------------
+// JsonFileStore
+// ----------------------------------------------------------------------------
+#[cfg(not(target_arch = "wasm32"))]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Store for JsonFileStore {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<Option<TreeNotesApp>, LoadError> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&self.path)?;
+        schema::load(&content).map(Some)
+    }
+
+    fn save(&mut self, app: &TreeNotesApp) -> Result<(), String> {
+        let json = serde_json::to_string(app).map_err(|e| e.to_string())?;
+        // バックアップの失敗で保存自体を止めない
+        if let Err(e) = backup::rotate(&self.path) {
+            log::warn!("Failed to create backup: {}", e);
+        }
+        write_atomic(&self.path, json.as_bytes())
+            .map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+
+    fn append_journal(&mut self, entry: &journal::Entry) -> Result<(), String> {
+        let path = journal::journal_path(&self.path);
+        journal::append(&path, entry).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn read_journal(&self) -> Result<Vec<journal::Entry>, String> {
+        let path = journal::journal_path(&self.path);
+        journal::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        backup::list_backups(&self.path)
+    }
+
+    fn create_snapshot(&mut self) -> Result<(), String> {
+        backup::create_backup(&self.path)
+            .map(|_| ())
+            .map_err(|e| format!("failed to create backup: {}", e))
+    }
+
+    fn load_snapshot(&self, id: &str) -> Result<TreeNotesApp, String> {
+        backup::load_backup(Path::new(id))
+    }
+
+    fn quarantine(&mut self) -> Result<String, String> {
+        backup::quarantine(&self.path)
+            .map(|p| p.display().to_string())
+            .map_err(|e| format!("failed to move the file aside: {}", e))
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// DirectoryStore
+// ----------------------------------------------------------------------------
+/// リポジトリ内のトラッカー。履歴は git に任せるのでスナップショットは持たず、
+/// 壊れていても丸ごと動かさない。
+#[cfg(not(target_arch = "wasm32"))]
+pub struct DirectoryStore {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Store for DirectoryStore {
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn load(&self) -> Result<Option<TreeNotesApp>, LoadError> {
+        if !self.dir.exists() {
+            return Ok(None);
+        }
+        dir_store::load(&self.dir).map(Some)
+    }
+
+    fn save(&mut self, app: &TreeNotesApp) -> Result<(), String> {
+        dir_store::save(&self.dir, app).map_err(|e| format!("{}: {}", self.dir.display(), e))
+    }
+
+    fn append_journal(&mut self, entry: &journal::Entry) -> Result<(), String> {
+        let path = dir_store::journal_path(&self.dir);
+        journal::append(&path, entry).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn read_journal(&self) -> Result<Vec<journal::Entry>, String> {
+        let path = dir_store::journal_path(&self.dir);
+        journal::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// EframeStore
+// ----------------------------------------------------------------------------
+/// eframe の web 版が使う localStorage のキー (`eframe::APP_KEY`) に直接書く。
+/// eframe 経由 (`eframe::App::save`) だと 30 秒ごとかページを閉じるときにしか書かれず、
+/// 閉じたときに呼ばれないこともあるので、保存のたびにその場で書き込む。
+#[cfg(target_arch = "wasm32")]
+pub struct EframeStore {
+    storage: Option<web_sys::Storage>,
+}
+
+/// 読めなかった storage の中身を退避するキー
+#[cfg(target_arch = "wasm32")]
+const CORRUPT_STORAGE_KEY: &str = "app_corrupt";
+
+#[cfg(target_arch = "wasm32")]
+impl EframeStore {
+    pub fn new() -> Self {
+        Self {
+            storage: web_sys::window().and_then(|w| w.local_storage().ok().flatten()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.storage.as_ref()?.get_item(key).ok().flatten()
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), String> {
+        let storage = self
+            .storage
+            .as_ref()
+            .ok_or_else(|| "localStorage is not available".to_string())?;
+        storage
+            .set_item(key, value)
+            .map_err(|e| format!("could not write localStorage: {:?}", e))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Store for EframeStore {
+    fn describe(&self) -> String {
+        "app storage".to_string()
+    }
+
+    fn load(&self) -> Result<Option<TreeNotesApp>, LoadError> {
+        self.get(eframe::APP_KEY)
+            .as_deref()
+            .map(schema::load)
+            .transpose()
+    }
+
+    fn save(&mut self, app: &TreeNotesApp) -> Result<(), String> {
+        let json = serde_json::to_string(app).map_err(|e| e.to_string())?;
+        self.set(eframe::APP_KEY, &json)
+    }
+
+    fn quarantine(&mut self) -> Result<String, String> {
+        let json = self
+            .get(eframe::APP_KEY)
+            .ok_or_else(|| "nothing to move aside".to_string())?;
+        self.set(CORRUPT_STORAGE_KEY, &json)?;
+        if let Some(storage) = &self.storage {
+            let _ = storage.remove_item(eframe::APP_KEY);
+        }
+        Ok(format!("storage key \"{}\"", CORRUPT_STORAGE_KEY))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// MemoryStore
+// ----------------------------------------------------------------------------
+/// 保存のたびに前の内容をスナップショットとして残す
+#[derive(Default)]
+pub struct MemoryStore {
+    current: Option<String>,
+    /// (識別子, 日時, JSON)。識別子は古いものを捨ててもずれないよう通し番号にする。
+    snapshots: Vec<(usize, DateTime<Local>, String)>,
+    next_snapshot: usize,
+    /// JSON Lines
+    journal: String,
+}
+
+/// `MemoryStore` が残すスナップショットの最大数
+const MAX_MEMORY_SNAPSHOTS: usize = 10;
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn describe(&self) -> String {
+        "memory (not saved to disk)".to_string()
+    }
+
+    fn load(&self) -> Result<Option<TreeNotesApp>, LoadError> {
+        self.current.as_deref().map(schema::load).transpose()
+    }
+
+    fn save(&mut self, app: &TreeNotesApp) -> Result<(), String> {
+        let json = serde_json::to_string(app).map_err(|e| e.to_string())?;
+        self.create_snapshot()?;
+        self.current = Some(json);
+        Ok(())
+    }
+
+    fn append_journal(&mut self, entry: &journal::Entry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        self.journal.push_str(&line);
+        self.journal.push('\n');
+        Ok(())
+    }
+
+    fn read_journal(&self) -> Result<Vec<journal::Entry>, String> {
+        Ok(journal::parse_lines(&self.journal))
+    }
+
+    fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.snapshots
+            .iter()
+            .rev()
+            .map(|(id, modified, json)| {
+                let app = schema::load(json).ok();
+                SnapshotInfo {
+                    id: id.to_string(),
+                    modified: *modified,
+                    issue_count: app.as_ref().map(|a| a.issues.get_list().len()),
+                    user_count: app.as_ref().map(|a| a.users.get_list().len()),
+                }
+            })
+            .collect()
+    }
+
+    fn create_snapshot(&mut self) -> Result<(), String> {
+        if let Some(json) = &self.current {
+            self.snapshots
+                .push((self.next_snapshot, Local::now(), json.clone()));
+            self.next_snapshot += 1;
+            if self.snapshots.len() > MAX_MEMORY_SNAPSHOTS {
+                self.snapshots.remove(0);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_snapshot(&self, id: &str) -> Result<TreeNotesApp, String> {
+        let (_, _, json) = id
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| self.snapshots.iter().find(|(i, _, _)| *i == id))
+            .ok_or_else(|| format!("snapshot {} not found", id))?;
+        schema::load(json).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// 番号をタイトルにした `count` 件の Issue
+    fn app_with_issues(count: usize) -> TreeNotesApp {
+        let titles: Vec<String> = (0..count).map(|i| i.to_string()).collect();
+        fixtures::app_with_issues(&titles)
+    }
+
+    #[test]
+    fn memory_store_keeps_what_was_saved() {
+        let mut store = MemoryStore::new();
+        assert!(store.load().unwrap().is_none());
+        store.save(&app_with_issues(2)).unwrap();
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.issues.get_list().len(), 3);
+    }
+
+    #[test]
+    fn memory_store_snapshots_the_previous_save() {
+        let mut store = MemoryStore::new();
+        store.save(&app_with_issues(1)).unwrap();
+        assert!(store.list_snapshots().is_empty());
+        store.save(&app_with_issues(2)).unwrap();
+
+        let snapshots = store.list_snapshots();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].issue_count, Some(2));
+        let restored = store.load_snapshot(&snapshots[0].id).unwrap();
+        assert_eq!(restored.issues.get_list().len(), 2);
+    }
+
+    #[test]
+    fn memory_store_snapshot_ids_survive_dropping_old_ones() {
+        let mut store = MemoryStore::new();
+        for count in 0..MAX_MEMORY_SNAPSHOTS + 3 {
+            store.save(&app_with_issues(count)).unwrap();
+        }
+        let snapshots = store.list_snapshots();
+        assert_eq!(snapshots.len(), MAX_MEMORY_SNAPSHOTS);
+        // 新しい順。直前の保存 (Issue が MAX + 1 件) が先頭に来る
+        let newest = store.load_snapshot(&snapshots[0].id).unwrap();
+        assert_eq!(newest.issues.get_list().len(), MAX_MEMORY_SNAPSHOTS + 2);
+        let oldest = store.load_snapshot(&snapshots[MAX_MEMORY_SNAPSHOTS - 1].id);
+        assert_eq!(oldest.unwrap().issues.get_list().len(), 3);
+    }
+}
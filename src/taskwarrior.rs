@@ -0,0 +1,222 @@
+// ----------------------------------------------------------------------------
+// Taskwarrior の書き出しと取り込み
+// ----------------------------------------------------------------------------
+//
+// `task export` の JSON (タスクの配列) を読み書きする。書き出したものは `task import` で読める。
+//
+// - 状態: Open = pending, 完了 = completed, 見送り = deleted
+// - ラベルは tags、コメントは annotations にする (タイトルを繰り返した最初のコメントも含め全て)
+// - fork 済みの区別と fork 元は UDA (`fork_notes_*`) に残す
+//
+// annotation には書いた人が無いので、取り込んだコメントは全て取り込んだ人のものになる。
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use local_issues_lib::{
+    Comment, Issue, Issues, Status,
+    user::{User, Users},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::convert::{self, Builder};
+use crate::ids::IssueIds;
+use crate::trash::Trash;
+
+/// Taskwarrior の日時の形式 (UTC)
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Deserialize, Serialize)]
+struct Annotation {
+    entry: String,
+    description: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Task {
+    description: String,
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    /// このアプリでの番号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fork_notes_id: Option<usize>,
+    /// fork 元の番号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fork_notes_parent: Option<usize>,
+    /// `status` だけでは区別できない状態 (`forked`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fork_notes_status: Option<String>,
+}
+
+fn format_date(date: &DateTime<Local>) -> String {
+    date.with_timezone(&Utc).format(DATE_FORMAT).to_string()
+}
+
+fn parse_date(text: &str) -> Option<DateTime<Local>> {
+    NaiveDateTime::parse_from_str(text, DATE_FORMAT)
+        .ok()
+        .map(|date| date.and_utc().with_timezone(&Local))
+}
+
+/// タグに空白は使えないので `_` にする
+fn tag(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// `number` と `parent` はこのアプリでの番号。`comments` は書き出すコメント。
+fn to_task(issue: &Issue, comments: &[&Comment], number: usize, parent: Option<usize>) -> Task {
+    let status = match issue.status() {
+        Status::Open => "pending",
+        Status::CloseAsCmp | Status::CloseAsForked => "completed",
+        Status::CloseAsNotPlaned => "deleted",
+    };
+    let end = match issue.status() {
+        Status::Open => None,
+        // 閉じた日時は残っていないので最後のコメントの日時にする
+        _ => comments.last().map(|c| format_date(&c.date())),
+    };
+    Task {
+        description: issue.name().to_string(),
+        status: status.to_string(),
+        entry: Some(format_date(issue.created_at())),
+        end,
+        tags: issue.get_labels().iter().map(|l| tag(l)).collect(),
+        annotations: comments
+            .iter()
+            .map(|c| Annotation {
+                entry: format_date(&c.date()),
+                description: c.text().to_string(),
+            })
+            .collect(),
+        fork_notes_id: Some(number),
+        fork_notes_parent: parent,
+        fork_notes_status: (issue.status() == &Status::CloseAsForked).then(|| "forked".to_string()),
+    }
+}
+
+/// ゴミ箱に入っていない Issue をタスクの配列にする
+pub fn render(issues: &Issues, ids: &IssueIds, trash: &Trash) -> String {
+    let tasks: Vec<Task> = trash
+        .kept_issues(issues, ids)
+        .into_iter()
+        .map(|i| {
+            let issue = &issues.get_list()[i];
+            let parent = trash.kept_parent(issues, ids, i).map(|p| ids.number(p));
+            to_task(
+                issue,
+                &trash.kept_comments(ids, i, issue),
+                ids.number(i),
+                parent,
+            )
+        })
+        .collect();
+    serde_json::to_string_pretty(&tasks).unwrap_or_default() + "\n"
+}
+
+/// Taskwarrior の JSON らしいか (タスクの配列か)。GitHub の JSON と見分けるのに使う。
+pub fn looks_like(json: &str) -> bool {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(json) else {
+        return false;
+    };
+    items.first().is_some_and(|item| {
+        item.get("description").is_some()
+            && item.get("status").is_some()
+            && item.get("title").is_none()
+    })
+}
+
+/// 1件のタスクを1件の Issue にする。作成者とコメントを書いた人は `author` にする。
+pub fn convert(json: &str, author: &User) -> Result<(Issues, Users), String> {
+    let tasks: Vec<Task> =
+        serde_json::from_str(json).map_err(|e| format!("not a Taskwarrior export: {}", e))?;
+
+    let mut builder = Builder::default();
+    builder.add_user(author);
+    for task in tasks {
+        let created_at = task
+            .entry
+            .as_deref()
+            .and_then(parse_date)
+            .unwrap_or_else(Local::now);
+        let mut issue = Issue::new(&task.description, author.clone(), task.tags);
+        issue = convert::with_created_at(&issue, &created_at)?;
+        // Taskwarrior で作ったタスクは説明を最初のコメントにする。
+        // このアプリで書き出したものはコメントを全て annotation にしているので足さない。
+        if task.fork_notes_id.is_none() && task.annotations.is_empty() {
+            issue.comment(convert::comment_at(
+                &task.description,
+                author.clone(),
+                &created_at,
+            )?);
+        }
+        for annotation in &task.annotations {
+            let date = parse_date(&annotation.entry).unwrap_or(created_at);
+            issue.comment(convert::comment_at(
+                &annotation.description,
+                author.clone(),
+                &date,
+            )?);
+        }
+
+        let status = match (task.status.as_str(), task.fork_notes_status.as_deref()) {
+            ("completed", Some("forked")) => Status::CloseAsForked,
+            ("completed", _) => Status::CloseAsCmp,
+            ("deleted", _) => Status::CloseAsNotPlaned,
+            _ => Status::Open,
+        };
+        builder.push(
+            convert::with_status(issue, &status)?,
+            task.fork_notes_id,
+            task.fork_notes_parent,
+        );
+    }
+    if builder.is_empty() {
+        return Err("no tasks found".to_string());
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeNotesApp;
+    use crate::fixtures;
+
+    #[test]
+    fn render_skips_the_root_issue() {
+        let app = fixtures::app_with_issues(&["only"]);
+
+        let out = render(&app.issues, &app.ids, &app.trash);
+        let tasks: Vec<Task> = serde_json::from_str(&out).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].description, "only");
+    }
+
+    #[test]
+    fn statuses_annotations_and_forks_survive_a_round_trip() {
+        let mut app = TreeNotesApp::default();
+        let mut issue = fixtures::issue(&app, "parent", &["needs review"]);
+        issue.comment(Comment::new("a note", app.current_user.clone()));
+        let parent = fixtures::add(&mut app, issue);
+        let child = fixtures::fork(&mut app, parent);
+
+        let out = render(&app.issues, &app.ids, &app.trash);
+        let (imported, _) = convert(&out, &app.current_user).unwrap();
+        let mut imported_ids = IssueIds::default();
+        imported_ids.sync(&imported);
+
+        let parent_issue = imported.get(parent).unwrap();
+        assert_eq!(parent_issue.status(), &Status::CloseAsForked);
+        assert_eq!(parent_issue.get_labels(), ["needs_review"]);
+        let texts: Vec<String> = parent_issue.comments().iter().map(|c| c.text()).collect();
+        assert_eq!(texts, ["parent", "a note"]);
+        assert_eq!(imported.get(child).unwrap().status(), &Status::Open);
+        assert_eq!(imported_ids.parent(&imported, child), Some(parent));
+    }
+}
@@ -0,0 +1,207 @@
+// ----------------------------------------------------------------------------
+// todo.txt の書き出しと取り込み
+// ----------------------------------------------------------------------------
+//
+// 1行が1件の Issue。<https://github.com/todotxt/todo.txt> の形式に従う。
+//
+//     x 2024-01-03 2024-01-01 Fix the parser +bug +ui id:2 parent:1 status:not_planned
+//
+// - 閉じている Issue は `x` と完了日 (最後のコメントの日付) を付ける
+// - ラベルは `+project` にする。取り込みでは `@context` もラベルとして読む
+// - 完了 / 見送り / fork 済みの区別と fork 元は `status:` `id:` `parent:` で残す
+//
+// 1行に収まらないコメントは書き出せないので、取り込んだ Issue のコメントはタイトルだけになる。
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use local_issues_lib::{
+    Comment, Issue, Issues, Status,
+    user::{User, Users},
+};
+
+use crate::convert::{self, Builder};
+use crate::ids::IssueIds;
+use crate::trash::Trash;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// `status:` に書く値。`x` だけなら完了とみなす。
+fn status_value(status: &Status) -> Option<&'static str> {
+    match status {
+        Status::CloseAsNotPlaned => Some("not_planned"),
+        Status::CloseAsForked => Some("forked"),
+        Status::Open | Status::CloseAsCmp => None,
+    }
+}
+
+/// 空白を含むと別の語になってしまうので `_` にする
+fn tag(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// ゴミ箱に入っていない Issue を1件1行にする
+pub fn render(issues: &Issues, ids: &IssueIds, trash: &Trash) -> String {
+    let mut out = String::new();
+    for i in trash.kept_issues(issues, ids) {
+        let issue = &issues.get_list()[i];
+        let mut words = Vec::new();
+        if issue.status() != &Status::Open {
+            words.push("x".to_string());
+            let done = trash
+                .kept_comments(ids, i, issue)
+                .last()
+                .map(|c| c.date())
+                .unwrap_or(*issue.created_at());
+            words.push(done.format(DATE_FORMAT).to_string());
+        }
+        words.push(issue.created_at().format(DATE_FORMAT).to_string());
+        words.push(
+            issue
+                .name()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        words.extend(issue.get_labels().iter().map(|l| format!("+{}", tag(l))));
+        words.push(format!("id:{}", ids.number(i)));
+        if let Some(parent) = trash.kept_parent(issues, ids, i) {
+            words.push(format!("parent:{}", ids.number(parent)));
+        }
+        if let Some(status) = status_value(issue.status()) {
+            words.push(format!("status:{}", status));
+        }
+        out.push_str(&words.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_date(word: &str) -> Option<DateTime<Local>> {
+    let date = NaiveDate::parse_from_str(word, DATE_FORMAT).ok()?;
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+}
+
+struct Line {
+    title: String,
+    labels: Vec<String>,
+    status: Status,
+    created_at: Option<DateTime<Local>>,
+    id: Option<usize>,
+    parent: Option<usize>,
+}
+
+/// 空行は None
+fn parse_line(line: &str) -> Option<Line> {
+    let mut words = line.split_whitespace().peekable();
+    words.peek()?;
+
+    let done = words.next_if_eq(&"x").is_some();
+    if !done {
+        // 優先度 `(A)` は使わない
+        words.next_if(|w| w.len() == 3 && w.starts_with('(') && w.ends_with(')'));
+    }
+    // 完了している場合は 完了日 作成日 の順。作成日だけの場合もある。
+    let first = words
+        .next_if(|w| parse_date(w).is_some())
+        .and_then(parse_date);
+    let second = words
+        .next_if(|w| parse_date(w).is_some())
+        .and_then(parse_date);
+    let created_at = if done { second.or(first) } else { first };
+
+    let mut title = Vec::new();
+    let mut labels = Vec::new();
+    let mut status = if done {
+        Status::CloseAsCmp
+    } else {
+        Status::Open
+    };
+    let (mut id, mut parent) = (None, None);
+    for word in words {
+        match word.split_once(':') {
+            Some(("id", value)) if value.parse::<usize>().is_ok() => id = value.parse().ok(),
+            Some(("parent", value)) if value.parse::<usize>().is_ok() => {
+                parent = value.parse().ok()
+            }
+            Some(("status", "not_planned")) if done => status = Status::CloseAsNotPlaned,
+            Some(("status", "forked")) if done => status = Status::CloseAsForked,
+            _ if word.len() > 1 && (word.starts_with('+') || word.starts_with('@')) => {
+                labels.push(word[1..].to_string())
+            }
+            _ => title.push(word),
+        }
+    }
+
+    Some(Line {
+        title: title.join(" "),
+        labels,
+        status,
+        created_at,
+        id,
+        parent,
+    })
+}
+
+/// 1行を1件の Issue にする。作成者は `author` にする。
+pub fn convert(text: &str, author: &User) -> Result<(Issues, Users), String> {
+    let mut builder = Builder::default();
+    builder.add_user(author);
+    for line in text.lines().filter_map(parse_line) {
+        if line.title.is_empty() {
+            continue;
+        }
+        let mut issue = Issue::new(&line.title, author.clone(), line.labels);
+        match line.created_at {
+            Some(created_at) => {
+                issue = convert::with_created_at(&issue, &created_at)?;
+                issue.comment(convert::comment_at(
+                    &line.title,
+                    author.clone(),
+                    &created_at,
+                )?);
+            }
+            None => issue.comment(Comment::new(line.title.clone(), author.clone())),
+        }
+        builder.push(
+            convert::with_status(issue, &line.status)?,
+            line.id,
+            line.parent,
+        );
+    }
+    if builder.is_empty() {
+        return Err("no tasks found".to_string());
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn render_skips_the_root_issue() {
+        let app = fixtures::app_with_issues(&["only"]);
+
+        let out = render(&app.issues, &app.ids, &app.trash);
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.contains("only") && !out.contains("root"));
+    }
+
+    #[test]
+    fn forks_survive_a_round_trip() {
+        let mut app = fixtures::app_with_issues(&["parent"]);
+        let parent = 1;
+        let child = fixtures::fork(&mut app, parent);
+
+        let out = render(&app.issues, &app.ids, &app.trash);
+        let (imported, _) = convert(&out, &app.current_user).unwrap();
+        let mut imported_ids = IssueIds::default();
+        imported_ids.sync(&imported);
+
+        assert_eq!(imported.get_list().len(), app.issues.get_list().len());
+        assert_eq!(imported_ids.parent(&imported, child), Some(parent));
+        assert_eq!(imported.get(child).unwrap().status(), &Status::Open);
+    }
+}
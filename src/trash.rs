@@ -0,0 +1,177 @@
+// ----------------------------------------------------------------------------
+// ゴミ箱
+// ----------------------------------------------------------------------------
+//
+// Issue やコメントを消しても `Issues` からは取り除かない。取り除くと後ろの index と
+// fork 元 (`from_index`) の参照がずれるので、ここに消したことだけを Issue の UUID (ids.rs) ごとに
+// 記録して表示を変える。
+//
+// - ゴミ箱に入れたもの: 中身はそのまま残り、元に戻せる
+// - 完全に削除したもの: 本文を空にして位置だけを残す。fork 元としては「削除された Issue」になる
+
+use chrono::{DateTime, Local};
+use local_issues_lib::{Comment, Issue, Issues, user::User};
+use serde::{Deserialize, Serialize};
+
+use crate::ids::IssueIds;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Deleted {
+    /// Issue の UUID (ids.rs)
+    pub issue: String,
+    /// None なら Issue そのもの
+    pub comment: Option<usize>,
+    pub by: User,
+    pub date: DateTime<Local>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Trash {
+    /// ゴミ箱に入っているもの (消した順)
+    deleted: Vec<Deleted>,
+    /// 完全に削除したもの (Issue の UUID, コメントの位置)
+    purged: Vec<(String, Option<usize>)>,
+}
+
+impl Trash {
+    pub fn get_list(&self) -> &Vec<Deleted> {
+        &self.deleted
+    }
+
+    pub fn get_purged(&self) -> &Vec<(String, Option<usize>)> {
+        &self.purged
+    }
+
+    pub fn get(&self, issue: &str, comment: Option<usize>) -> Option<&Deleted> {
+        self.deleted
+            .iter()
+            .find(|d| d.issue == issue && d.comment == comment)
+    }
+
+    /// ゴミ箱に入っているか、完全に削除されているか
+    pub fn is_deleted(&self, issue: &str, comment: Option<usize>) -> bool {
+        self.get(issue, comment).is_some() || self.is_purged(issue, comment)
+    }
+
+    pub fn is_purged(&self, issue: &str, comment: Option<usize>) -> bool {
+        self.purged
+            .iter()
+            .any(|(i, c)| i == issue && (c.is_none() || *c == comment))
+    }
+
+    pub fn delete(&mut self, deleted: Deleted) {
+        if !self.is_deleted(&deleted.issue, deleted.comment) {
+            self.deleted.push(deleted);
+        }
+    }
+
+    /// 元に戻す。ゴミ箱に無ければ false。
+    pub fn restore(&mut self, issue: &str, comment: Option<usize>) -> bool {
+        let before = self.deleted.len();
+        self.deleted
+            .retain(|d| !(d.issue == issue && d.comment == comment));
+        self.deleted.len() != before
+    }
+
+    /// 完全に削除したことにする。Issue の場合はそのコメントもゴミ箱から消える。
+    pub fn purge(&mut self, issue: &str, comment: Option<usize>) {
+        self.deleted
+            .retain(|d| d.issue != issue || (comment.is_some() && d.comment != comment));
+        if !self.purged.iter().any(|(i, c)| i == issue && *c == comment) {
+            self.purged.push((issue.to_string(), comment));
+        }
+    }
+
+    /// `index` 番目の Issue (のコメント) が消されているか
+    pub fn is_deleted_at(&self, ids: &IssueIds, index: usize, comment: Option<usize>) -> bool {
+        ids.uuid(index)
+            .is_some_and(|uuid| self.is_deleted(uuid, comment))
+    }
+
+    // 書き出し用。ゴミ箱のもの・完全に削除したものは書き出さない。
+
+    /// 書き出す Issue の位置。`Issues::new()` が 0 番目に作る root は Issue ではないので含めない。
+    pub fn kept_issues(&self, issues: &Issues, ids: &IssueIds) -> Vec<usize> {
+        (1..issues.get_list().len())
+            .filter(|&i| !self.is_deleted_at(ids, i, None))
+            .collect()
+    }
+
+    /// `index` 番目の Issue の、消されていないコメント
+    pub fn kept_comments<'a>(
+        &self,
+        ids: &IssueIds,
+        index: usize,
+        issue: &'a Issue,
+    ) -> Vec<&'a Comment> {
+        issue
+            .comments()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.is_deleted_at(ids, index, Some(*i)))
+            .map(|(_, comment)| comment)
+            .collect()
+    }
+
+    /// fork 元。消されていれば None
+    pub fn kept_parent(&self, issues: &Issues, ids: &IssueIds, index: usize) -> Option<usize> {
+        ids.parent(issues, index)
+            .filter(|&parent| !self.is_deleted_at(ids, parent, None))
+    }
+
+    /// 消されていない fork 先
+    pub fn kept_children(&self, issues: &Issues, ids: &IssueIds, index: usize) -> Vec<usize> {
+        ids.children(issues, index)
+            .into_iter()
+            .filter(|&child| !self.is_deleted_at(ids, child, None))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeNotesApp;
+    use crate::fixtures;
+    use crate::journal::Op;
+
+    #[test]
+    fn exports_skip_deleted_issues_comments_and_parents() {
+        let mut app = fixtures::app_with_issues(&["parent"]);
+        let parent = 1;
+        Op::Comment {
+            issue: app.ids.key(parent),
+            comment: Comment::new("secret", app.current_user.clone()),
+        }
+        .apply(&mut app);
+        let child = fixtures::fork(&mut app, parent);
+        let delete = |app: &TreeNotesApp, comment| Op::Delete {
+            issue: app.ids.key(parent),
+            comment,
+            by: app.current_user.clone(),
+            date: Local::now(),
+        };
+
+        delete(&app, Some(1)).apply(&mut app);
+        let (issues, ids, trash) = (&app.issues, &app.ids, &app.trash);
+        let comments = trash.kept_comments(ids, parent, issues.get(parent).unwrap());
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].text(), "parent");
+        assert_eq!(trash.kept_parent(issues, ids, child), Some(parent));
+        assert_eq!(trash.kept_children(issues, ids, parent), vec![child]);
+
+        delete(&app, None).apply(&mut app);
+        let (issues, ids, trash) = (&app.issues, &app.ids, &app.trash);
+        assert!(!trash.kept_issues(issues, ids).contains(&parent));
+        assert_eq!(trash.kept_parent(issues, ids, child), None);
+
+        let csv = crate::csv::render(issues, ids, trash);
+        let numbers: Vec<String> = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap_or_default().to_string())
+            .collect();
+        assert!(!numbers.contains(&ids.number(parent).to_string()));
+        assert!(numbers.contains(&ids.number(child).to_string()));
+    }
+}
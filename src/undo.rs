@@ -0,0 +1,245 @@
+// ----------------------------------------------------------------------------
+// 取り消し / やり直し
+// ----------------------------------------------------------------------------
+//
+// 取り消せる一番古い操作の前の Issues・Users・ラベルの登録簿・編集履歴・ゴミ箱・番号・担当者を
+// 1つだけ覚えておき (`base`)、取り消すときはそこに残りの操作を適用し直した状態に戻す。
+// fork のように新しい Issue を作りつつ元の Issue も閉じる操作や、マージのように
+// 複数の `Op` をまとめて適用したものも1回で元に戻る。
+// 取り消したことはジャーナルに `Op::Undo` として残し、起動時のやり直しでは取り消した操作を飛ばす。
+// やり直しは同じ `Op` をもう一度適用する (ジャーナルには新しい操作として書かれる)。
+
+use local_issues_lib::{Issues, user::Users};
+use serde_json::Value;
+
+use crate::TreeNotesApp;
+use crate::assignees::Assignees;
+use crate::ids::IssueIds;
+use crate::journal::Op;
+use crate::labels::Labels;
+use crate::revisions::Revisions;
+use crate::trash::Trash;
+
+/// 取り消せる操作の数
+const MAX_UNDO: usize = 100;
+
+pub struct Step {
+    /// メニューに出す説明
+    pub label: String,
+    pub ops: Vec<Op>,
+    /// `ops` を書いたジャーナルの seq
+    pub seqs: Vec<u64>,
+    /// 適用する前に選択していた Issue の番号
+    pub selected: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct UndoStack {
+    /// `undo` の最初の操作を適用する前の状態 (`capture`)
+    base: Option<Value>,
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+/// 取り消しで戻す部分 `(issues, users, labels, revisions, trash, ids, assignees)` を JSON にする
+pub fn capture(app: &TreeNotesApp) -> Result<Value, String> {
+    serde_json::to_value((
+        &app.issues,
+        &app.users,
+        &app.labels,
+        &app.revisions,
+        &app.trash,
+        &app.ids,
+        &app.assignees,
+    ))
+    .map_err(|e| e.to_string())
+}
+
+/// `capture` した状態に `steps` の操作を順に適用する
+fn rebuild(base: &Value, steps: &[Step]) -> Result<TreeNotesApp, String> {
+    let (issues, users, labels, revisions, trash, ids, assignees) =
+        serde_json::from_value::<(Issues, Users, Labels, Revisions, Trash, IssueIds, Assignees)>(
+            base.clone(),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut app = TreeNotesApp {
+        issues,
+        users,
+        labels,
+        revisions,
+        trash,
+        ids,
+        assignees,
+        ..TreeNotesApp::default()
+    };
+    for op in steps.iter().flat_map(|step| step.ops.iter().cloned()) {
+        op.apply(&mut app);
+    }
+    Ok(app)
+}
+
+impl UndoStack {
+    /// 取り消せる操作が無いときは、次の操作の前に `set_base` で今の状態を渡す
+    pub fn needs_base(&self) -> bool {
+        self.base.is_none() || self.undo.is_empty()
+    }
+
+    pub fn set_base(&mut self, base: Value) {
+        self.base = Some(base);
+    }
+
+    /// 数を超えたら一番古い操作を `base` に含めてしまう
+    pub fn push(&mut self, step: Step) {
+        self.undo.push(step);
+        if self.undo.len() <= MAX_UNDO {
+            return;
+        }
+        let oldest = self.undo.remove(0);
+        let folded = self
+            .base
+            .as_ref()
+            .ok_or_else(|| "nothing to undo from".to_string())
+            .and_then(|base| rebuild(base, std::slice::from_ref(&oldest)))
+            .and_then(|app| capture(&app));
+        match folded {
+            Ok(base) => self.base = Some(base),
+            Err(e) => {
+                log::error!("Failed to keep the undo history: {}", e);
+                self.clear();
+            }
+        }
+    }
+
+    /// 最後の操作を取り出し、それを除いた状態を返す。失敗したら取り消せる操作はそのまま。
+    pub fn undo(&mut self) -> Option<Result<(Step, TreeNotesApp), String>> {
+        let base = self.base.as_ref()?;
+        let step = self.undo.pop()?;
+        match rebuild(base, &self.undo) {
+            Ok(app) => Some(Ok((step, app))),
+            Err(e) => {
+                self.undo.push(step);
+                Some(Err(e))
+            }
+        }
+    }
+
+    pub fn push_redo(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Step> {
+        self.redo.pop()
+    }
+
+    /// 新しい操作をしたらやり直しはできなくなる
+    pub fn clear_redo(&mut self) {
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.base = None;
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// メニューに出す説明
+    pub fn undo_label(&self) -> Option<String> {
+        self.undo.last().map(|step| step.label.clone())
+    }
+
+    pub fn redo_label(&self) -> Option<String> {
+        self.redo.last().map(|step| step.label.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::ids::IssueRef;
+    use crate::journal::{self, Entry};
+    use crate::storage::{MemoryStore, Store};
+    use local_issues_lib::Status;
+
+    fn new_issue(app: &TreeNotesApp, title: &str) -> Op {
+        Op::AddNewIssue {
+            issue: fixtures::issue(app, title, &[]),
+            uuid: Some(crate::ids::new_uuid()),
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn undo_is_journaled_and_redo_applies_again() {
+        let mut app = TreeNotesApp::default();
+        app.perform(new_issue(&app, "a"));
+        app.perform(Op::CloseAsCmp {
+            issue: app.ids.key(1),
+        });
+
+        app.undo();
+        assert_eq!(app.issues.get_list()[1].status(), &Status::Open);
+        let entries = app.store.read_journal().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(app.journal_seq, 3);
+        assert!(journal::undone(&entries).contains(&2));
+        assert_eq!(app.undo.undo_label().as_deref(), Some("created \"a\""));
+
+        app.redo();
+        assert_eq!(app.issues.get_list()[1].status(), &Status::CloseAsCmp);
+        assert_eq!(app.store.read_journal().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn replay_skips_undone_entries() {
+        let mut store = MemoryStore::new();
+        let source = TreeNotesApp::default();
+        let user = source.current_user.clone();
+        for (seq, op) in [
+            new_issue(&source, "a"),
+            Op::CloseAsCmp {
+                issue: IssueRef::Index(1),
+            },
+            Op::Undo {
+                seqs: vec![2],
+                label: "closed #2 as completed".to_string(),
+            },
+            Op::Comment {
+                issue: IssueRef::Index(1),
+                comment: local_issues_lib::Comment::new("still open", user.clone()),
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            store
+                .append_journal(&Entry::new(seq as u64 + 1, &user, op))
+                .unwrap();
+        }
+
+        let mut app = TreeNotesApp {
+            store: Box::new(store),
+            ..TreeNotesApp::default()
+        };
+        app.replay_journal();
+        let issue = &app.issues.get_list()[1];
+        assert_eq!(issue.name(), "a");
+        assert_eq!(issue.status(), &Status::Open);
+        assert_eq!(issue.comments().len(), 2);
+        assert_eq!(app.journal_seq, 4);
+    }
+
+    #[test]
+    fn old_steps_fold_into_the_base() {
+        let mut app = TreeNotesApp::default();
+        for i in 0..MAX_UNDO + 5 {
+            app.perform(new_issue(&app, &i.to_string()));
+        }
+        while app.undo.undo_label().is_some() {
+            app.undo();
+        }
+        // 取り消せるのは新しい MAX_UNDO 件だけ
+        assert_eq!(app.issues.get_list().len(), 1 + 5);
+        assert_eq!(app.issues.get_list()[5].name(), "4");
+    }
+}
@@ -0,0 +1,129 @@
+// ----------------------------------------------------------------------------
+// ワークスペース (トラッカーファイルの切り替え)
+// ----------------------------------------------------------------------------
+//
+// - Default: `<data dir>/tree_notes/data.json`
+// - 名前付き: `<data dir>/tree_notes/workspaces/<name>/data.json`
+// - それ以外: 任意のパスにあるトラッカーファイル、またはリポジトリ内の `.fork_notes/`
+//
+// 最近開いたものと最後に開いたものは `<data dir>/tree_notes/workspaces.json` に残す。
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 最近開いたワークスペースとして覚えておく数
+const MAX_RECENT: usize = 10;
+const DATA_FILE_NAME: &str = "data.json";
+
+/// `tree_notes` ディレクトリ
+pub fn data_root() -> Option<PathBuf> {
+    let data_dir = if let Ok(xdg) = std::env::var("XDG_DATA_HOME") {
+        PathBuf::from(xdg)
+    } else {
+        let home = std::env::var("HOME").ok()?;
+        PathBuf::from(home).join(".local").join("share")
+    };
+    Some(data_dir.join("tree_notes"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    data_root().map(|root| root.join(DATA_FILE_NAME))
+}
+
+fn workspaces_dir() -> Option<PathBuf> {
+    data_root().map(|root| root.join("workspaces"))
+}
+
+/// ディレクトリ名として使えない文字を含む名前は弾く
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("\"{}\" can't be used as a workspace name", name));
+    }
+    Ok(())
+}
+
+pub fn named_path(name: &str) -> Option<PathBuf> {
+    workspaces_dir().map(|dir| dir.join(name.trim()).join(DATA_FILE_NAME))
+}
+
+/// 作成済みの名前付きワークスペースを名前順に返す
+pub fn list_named() -> Vec<String> {
+    let Some(Ok(entries)) = workspaces_dir().map(fs::read_dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// メニューなどに表示する名前
+pub fn display_name(path: &Path) -> String {
+    if default_path().as_deref() == Some(path) {
+        return "Default".to_string();
+    }
+    if let Some(dir) = workspaces_dir()
+        && path.file_name().is_some_and(|n| n == DATA_FILE_NAME)
+        && let Some(parent) = path.parent()
+        && parent.parent() == Some(dir.as_path())
+        && let Some(name) = parent.file_name()
+    {
+        return name.to_string_lossy().into_owned();
+    }
+    if crate::dir_store::is_tracker_dir(path)
+        && let Some(repo) = path.parent().and_then(|p| p.file_name())
+    {
+        return format!("{} (repository)", repo.to_string_lossy());
+    }
+    path.display().to_string()
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// 新しい順
+    pub recent: Vec<PathBuf>,
+    pub last: Option<PathBuf>,
+}
+
+impl WorkspaceConfig {
+    fn config_path() -> Option<PathBuf> {
+        data_root().map(|root| root.join("workspaces.json"))
+    }
+
+    pub fn load() -> Self {
+        Self::config_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path =
+            Self::config_path().ok_or_else(|| "could not determine data directory".to_string())?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        crate::storage::write_atomic(&path, json.as_bytes())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// 起動時に開くトラッカー
+    pub fn startup_path(&self) -> Option<PathBuf> {
+        self.last.clone().or_else(default_path)
+    }
+
+    /// `path` を最後に開いたものとして記録する
+    pub fn touch(&mut self, path: &Path) {
+        self.recent.retain(|p| p != path);
+        self.recent.insert(0, path.to_path_buf());
+        self.recent.truncate(MAX_RECENT);
+        self.last = Some(path.to_path_buf());
+    }
+}